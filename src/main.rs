@@ -1,52 +1,114 @@
 mod terminal_gl;
 
-use terminal_gl::camera::*;
 use terminal_gl::geometry::*;
-use terminal_gl::matrix::*;
+use terminal_gl::light::*;
+use terminal_gl::material::*;
 use terminal_gl::mesh::*;
+use terminal_gl::postprocess::*;
+use terminal_gl::recorder::*;
 use terminal_gl::renderer::*;
+use terminal_gl::scene_graph::*;
+use terminal_gl::shader::*;
+use terminal_gl::shadow::*;
+use terminal_gl::ssao::*;
+use terminal_gl::texture::*;
 use terminal_gl::*;
 
-use std::io::{self, Read};
+use std::env;
+use std::io::{self, Read, Write};
+use std::process::{Command, Stdio};
 use std::sync::mpsc;
 use std::thread;
 use std::time::{Duration, Instant};
 
+use std::collections::HashMap;
 use std::fs::File;
 use std::io::{BufRead, BufReader};
+use std::path::Path;
+use std::rc::Rc;
 
 impl Mesh {
     pub fn from_obj_file(filename: &str) -> std::io::Result<Self> {
         let file = File::open(filename)?;
         let reader = BufReader::new(file);
 
+        let mut positions = Vec::new();
+        let mut uvs = Vec::new();
+        let mut normals = Vec::new();
+
         let mut vertices = Vec::new();
         let mut triangles = Vec::new();
+        // (v, vt, vn) の組ごとに頂点を 1 つ作る
+        let mut vertex_indices: HashMap<(usize, Option<usize>, Option<usize>), usize> =
+            HashMap::new();
+
+        let parse_floats = |rest: &str| -> Vec<f32> {
+            rest.split_whitespace()
+                .map(|p| p.parse().unwrap_or(0.0))
+                .collect()
+        };
 
         for line in reader.lines() {
             let line = line?;
             let line = line.trim();
 
-            if line.starts_with("v ") {
+            if let Some(rest) = line.strip_prefix("v ") {
                 // 頂点情報
-                let parts: Vec<&str> = line[2..].split_whitespace().collect();
-                if parts.len() == 3 {
-                    let x: f32 = parts[0].parse().unwrap_or(0.0);
-                    let y: f32 = parts[1].parse().unwrap_or(0.0);
-                    let z: f32 = parts[2].parse().unwrap_or(0.0);
-                    vertices.push(Vertex::new(Vec3::new(x, y, z)));
+                let parts = parse_floats(rest);
+                if parts.len() >= 3 {
+                    positions.push(Vec3::new(parts[0], parts[1], parts[2]));
+                }
+            } else if let Some(rest) = line.strip_prefix("vt ") {
+                // テクスチャ座標
+                let parts = parse_floats(rest);
+                if parts.len() >= 2 {
+                    uvs.push(Vec2::new(parts[0], parts[1]));
+                }
+            } else if let Some(rest) = line.strip_prefix("vn ") {
+                // 法線
+                let parts = parse_floats(rest);
+                if parts.len() >= 3 {
+                    normals.push(Vec3::new(parts[0], parts[1], parts[2]).normalize());
+                }
+            } else if let Some(rest) = line.strip_prefix("f ") {
+                // 面情報 (多角形は扇形に三角形分割する)
+                let mut face = Vec::new();
+                for part in rest.split_whitespace() {
+                    let mut fields = part.split('/');
+                    let index = |field: Option<&str>| {
+                        field
+                            .and_then(|f| f.parse::<usize>().ok())
+                            .filter(|&i| i > 0)
+                            .map(|i| i - 1)
+                    };
+                    let Some(v) = index(fields.next()) else {
+                        continue;
+                    };
+                    let vt = index(fields.next()).filter(|&i| i < uvs.len());
+                    let vn = index(fields.next()).filter(|&i| i < normals.len());
+                    if v >= positions.len() {
+                        continue;
+                    }
+
+                    let vertex = *vertex_indices.entry((v, vt, vn)).or_insert_with(|| {
+                        let mut vertex = Vertex::new(positions[v]);
+                        if let Some(vt) = vt {
+                            vertex.uv = uvs[vt];
+                        }
+                        if let Some(vn) = vn {
+                            vertex.normal = normals[vn];
+                        }
+                        vertices.push(vertex);
+                        vertices.len() - 1
+                    });
+                    face.push(vertex);
                 }
-            } else if line.starts_with("f ") {
-                // 面情報 (三角形のみ対応)
-                let parts: Vec<&str> = line[2..].split_whitespace().collect();
-                if parts.len() == 3 {
-                    let idx: Vec<usize> = parts
-                        .iter()
-                        .map(|p| p.split('/').next().unwrap().parse::<usize>().unwrap() - 1)
-                        .collect();
+
+                for i in 1..face.len().saturating_sub(1) {
                     triangles.push(Triangle {
-                        vertices: [idx[0], idx[1], idx[2]],
+                        vertices: [face[0], face[i], face[i + 1]],
                         color: Color::WHITE,
+                        material: None,
                     });
                 }
             }
@@ -55,24 +117,350 @@ impl Mesh {
         Ok(Self {
             vertices,
             triangles,
-            transform: Transform::new(),
+            ..Self::new()
         })
     }
 }
 
+// [--obj <file.obj>] [--texture <image>] [--filter nearest|bilinear|trilinear] [--wrap repeat|clamp|mirror] [--floor] [--grid]
+// [--shadows] [--lights] [--pbr] [--ssao] [--glass] [--blend alpha|add|multiply] [--oit] [--gradient] [--skybox <dir>] [--mode wireframe|filled|toon|overlay]
+// [--post gamma,tonemap,contrast,vignette,bloom,edges,crt,sepia] [--lut <file.cube>]
+// [--normals] [--hidden-line] [--feature-edges] [--aa] [--show-lights] [--depth-cue] [--fog linear|exp|exp2] [--ssaa N [--ssaa-filter box|tent] | --msaa N]
+// [--record <file.cast|file.gif>] [--frames N]
+struct Options {
+    obj_path: Option<String>,
+    texture_path: Option<String>,
+    texture_filter: Option<(Filter, MipFilter)>,
+    wrap: Option<WrapMode>,
+    floor: bool,
+    grid: bool,
+    shadows: bool,
+    colored_lights: bool,
+    pbr: bool,
+    ssao: bool,
+    glass: bool,
+    blend_mode: BlendMode,
+    oit: bool,
+    gradient: bool,
+    skybox_dir: Option<String>,
+    render_mode: RenderMode,
+    normals: bool,
+    hidden_line: bool,
+    feature_edges: bool,
+    anti_aliased: bool,
+    show_lights: bool,
+    antialiasing: Antialiasing,
+    ssaa_filter: DownsampleFilter,
+    depth_cue: bool,
+    fog: Option<FogMode>,
+    post_effects: Vec<PostEffect>,
+    record_path: Option<String>,
+    max_frames: Option<usize>,
+}
+
+fn post_effect(name: &str) -> Option<PostEffect> {
+    let effect = match name {
+        "gamma" => PostEffect::Gamma(2.2),
+        "tonemap" => PostEffect::ToneMap { exposure: 1.5 },
+        "contrast" => PostEffect::BrightnessContrast {
+            brightness: 0.0,
+            contrast: 1.3,
+        },
+        "vignette" => PostEffect::Vignette {
+            strength: 0.7,
+            radius: 0.4,
+        },
+        "bloom" => PostEffect::Bloom {
+            threshold: 180,
+            radius: 3,
+            intensity: 0.8,
+        },
+        "edges" => PostEffect::EdgeDetect {
+            threshold: 0.4,
+            color: Color::BLACK,
+        },
+        "crt" => PostEffect::Scanlines { darkness: 0.4 },
+        "sepia" => PostEffect::ColorGrade(Rc::new(Lut3d::from_fn(16, |[r, g, b]| {
+            let y = 0.299 * r + 0.587 * g + 0.114 * b;
+            [y * 1.07, y * 0.74 + 0.05, y * 0.43 + 0.05]
+        }))),
+        _ => return None,
+    };
+    Some(effect)
+}
+
+fn parse_options() -> Options {
+    let mut options = Options {
+        obj_path: None,
+        texture_path: None,
+        texture_filter: None,
+        wrap: None,
+        floor: false,
+        grid: false,
+        shadows: false,
+        colored_lights: false,
+        pbr: false,
+        ssao: false,
+        glass: false,
+        blend_mode: BlendMode::Alpha,
+        oit: false,
+        gradient: false,
+        skybox_dir: None,
+        render_mode: RenderMode::Wireframe,
+        normals: false,
+        hidden_line: false,
+        feature_edges: false,
+        anti_aliased: false,
+        show_lights: false,
+        antialiasing: Antialiasing::None,
+        ssaa_filter: DownsampleFilter::Tent,
+        depth_cue: false,
+        fog: None,
+        post_effects: Vec::new(),
+        record_path: None,
+        max_frames: None,
+    };
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--obj" => options.obj_path = args.next(),
+            "--texture" => options.texture_path = args.next(),
+            "--filter" => match args.next().as_deref() {
+                Some("nearest") => {
+                    options.texture_filter = Some((Filter::Nearest, MipFilter::None))
+                }
+                Some("bilinear") => {
+                    options.texture_filter = Some((Filter::Bilinear, MipFilter::Nearest))
+                }
+                Some("trilinear") => {
+                    options.texture_filter = Some((Filter::Bilinear, MipFilter::Linear))
+                }
+                other => eprintln!("Unknown texture filter: {:?}", other),
+            },
+            "--wrap" => match args.next().as_deref() {
+                Some("repeat") => options.wrap = Some(WrapMode::Repeat),
+                Some("clamp") => options.wrap = Some(WrapMode::Clamp),
+                Some("mirror") => options.wrap = Some(WrapMode::Mirror),
+                other => eprintln!("Unknown wrap mode: {:?}", other),
+            },
+            "--floor" => options.floor = true,
+            "--grid" => {
+                options.floor = true;
+                options.grid = true;
+            }
+            "--shadows" => options.shadows = true,
+            "--lights" => options.colored_lights = true,
+            "--pbr" => options.pbr = true,
+            "--ssao" => options.ssao = true,
+            "--glass" => options.glass = true,
+            "--blend" => match args.next().as_deref() {
+                Some("alpha") => options.blend_mode = BlendMode::Alpha,
+                Some("add") => options.blend_mode = BlendMode::Additive,
+                Some("multiply") => options.blend_mode = BlendMode::Multiply,
+                other => eprintln!("Unknown blend mode: {:?}", other),
+            },
+            "--oit" => options.oit = true,
+            "--gradient" => options.gradient = true,
+            "--skybox" => options.skybox_dir = args.next(),
+            "--mode" => match args.next().as_deref() {
+                Some("wireframe") => options.render_mode = RenderMode::Wireframe,
+                Some("filled") => options.render_mode = RenderMode::Filled,
+                Some("toon") => options.render_mode = RenderMode::Toon,
+                Some("overlay") => options.render_mode = RenderMode::FilledWireframe,
+                other => eprintln!("Unknown render mode: {:?}", other),
+            },
+            "--normals" => options.normals = true,
+            "--hidden-line" => options.hidden_line = true,
+            "--feature-edges" => options.feature_edges = true,
+            "--aa" => options.anti_aliased = true,
+            "--show-lights" => options.show_lights = true,
+            "--depth-cue" => options.depth_cue = true,
+            "--fog" => match args.next().as_deref() {
+                Some("linear") => {
+                    options.fog = Some(FogMode::Linear {
+                        start: 3.0,
+                        end: 12.0,
+                    })
+                }
+                Some("exp") => options.fog = Some(FogMode::Exponential { density: 0.15 }),
+                Some("exp2") => {
+                    options.fog = Some(FogMode::ExponentialSquared { density: 0.12 })
+                }
+                other => eprintln!("Unknown fog mode: {:?}", other),
+            },
+            "--post" => {
+                for name in args.next().unwrap_or_default().split(',') {
+                    match post_effect(name) {
+                        Some(effect) => options.post_effects.push(effect),
+                        None => eprintln!("Unknown post effect: {}", name),
+                    }
+                }
+            }
+            "--lut" => match args.next().map(Lut3d::load) {
+                Some(Ok(lut)) => options.post_effects.push(PostEffect::ColorGrade(Rc::new(lut))),
+                Some(Err(err)) => eprintln!("Failed to load LUT: {}", err),
+                None => eprintln!("--lut needs a .cube file"),
+            },
+            "--ssaa" => match args.next().and_then(|n| n.parse().ok()) {
+                Some(factor) => {
+                    options.antialiasing = Antialiasing::Ssaa {
+                        factor,
+                        filter: DownsampleFilter::Tent,
+                    }
+                }
+                None => eprintln!("--ssaa needs a factor"),
+            },
+            "--ssaa-filter" => match args.next().as_deref() {
+                Some("box") => options.ssaa_filter = DownsampleFilter::Box,
+                Some("tent") => options.ssaa_filter = DownsampleFilter::Tent,
+                other => eprintln!("Unknown SSAA filter: {:?}", other),
+            },
+            "--msaa" => match args.next().and_then(|n| n.parse().ok()) {
+                Some(samples) => options.antialiasing = Antialiasing::Msaa { samples },
+                None => eprintln!("--msaa needs a sample count"),
+            },
+            "--record" => options.record_path = args.next(),
+            "--frames" => options.max_frames = args.next().and_then(|n| n.parse().ok()),
+            _ => eprintln!("Unknown argument: {}", arg),
+        }
+    }
+
+    // --ssaa-filter は --ssaa より前に書いてもよい
+    if let Antialiasing::Ssaa { filter, .. } = &mut options.antialiasing {
+        *filter = options.ssaa_filter;
+    }
+
+    // 録画時はフレーム数を指定しなければ約 10 秒で止める
+    if options.record_path.is_some() && options.max_frames.is_none() {
+        options.max_frames = Some(300);
+    }
+
+    options
+}
+
+impl Options {
+    // --filter / --wrap で指定した部分だけ既定のサンプラーを置き換える
+    fn sampler(&self, mut sampler: Sampler) -> Sampler {
+        if let Some((filter, mip_filter)) = self.texture_filter {
+            sampler.filter = filter;
+            sampler.mip_filter = mip_filter;
+        }
+        if let Some(wrap) = self.wrap {
+            sampler.wrap_u = wrap;
+            sampler.wrap_v = wrap;
+        }
+        sampler
+    }
+}
+
+enum Key {
+    Char(u8),
+    Up,
+    Down,
+    Left,
+    Right,
+    Escape,
+}
+
+// 1 回の read で届いたバイト列をキーに分ける。矢印キーは ESC [ A..D で届く
+fn parse_keys(bytes: &[u8]) -> Vec<Key> {
+    let mut keys = Vec::new();
+    let mut i = 0;
+    while i < bytes.len() {
+        let arrow = match (bytes[i], bytes.get(i + 1..i + 3)) {
+            (0x1b, Some([b'[', b'A'])) => Some(Key::Up),
+            (0x1b, Some([b'[', b'B'])) => Some(Key::Down),
+            (0x1b, Some([b'[', b'C'])) => Some(Key::Right),
+            (0x1b, Some([b'[', b'D'])) => Some(Key::Left),
+            _ => None,
+        };
+        if let Some(key) = arrow {
+            keys.push(key);
+            i += 3;
+            continue;
+        }
+
+        keys.push(match bytes[i] {
+            0x1b => Key::Escape,
+            c => Key::Char(c.to_ascii_lowercase()),
+        });
+        i += 1;
+    }
+    keys
+}
+
+// 行バッファとエコーを切り、1 キーごとに読めるようにする (端末でなければ何もしない)
+fn set_raw_mode(enabled: bool) {
+    let args: &[&str] = if enabled {
+        &["-icanon", "-echo", "min", "1"]
+    } else {
+        &["icanon", "echo"]
+    };
+    let _ = Command::new("stty").args(args).stderr(Stdio::null()).status();
+}
+
+fn restore_terminal() {
+    print!("\x1b[?25h"); // カーソルを表示
+    print!("\x1b[?1049l"); // 元のスクリーンバッファに戻す
+    let _ = io::stdout().flush();
+    set_raw_mode(false);
+}
+
+// 描画を止めないように、キー入力は別スレッドで読んでチャネルで渡す
+fn spawn_input_thread() -> mpsc::Receiver<Vec<u8>> {
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        let mut buffer = [0; 16];
+        while let Ok(n) = io::stdin().read(&mut buffer) {
+            if n == 0 || sender.send(buffer[..n].to_vec()).is_err() {
+                break;
+            }
+        }
+    });
+    receiver
+}
+
+// dir 内の px, nx, py, ny, pz, nz (.png / .tga / .ppm) をキューブマップとして読む
+fn load_skybox(dir: &str) -> io::Result<CubeMap> {
+    let find = |name: &str| {
+        ["png", "tga", "ppm"]
+            .iter()
+            .map(|ext| Path::new(dir).join(format!("{}.{}", name, ext)))
+            .find(|path| path.exists())
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, format!("{} not found", name)))
+    };
+    CubeMap::load([
+        find("px")?,
+        find("nx")?,
+        find("py")?,
+        find("ny")?,
+        find("pz")?,
+        find("nz")?,
+    ])
+}
+
 fn main() {
+    let mut options = parse_options();
     println!("Terminal Tiny GL - Rust Edition");
     println!("Controls:");
     println!("  WASD: Move camera");
     println!("  QE: Move up/down");
     println!("  Arrow keys: Rotate camera");
-    println!("  R: Toggle render mode (wireframe/filled)");
+    println!("  ZX: Roll camera, C: Level camera");
+    println!("  +/-: Zoom in/out");
+    println!("  R: Toggle render mode (wireframe/filled/toon/overlay)");
     println!("  F: Toggle FPS display");
     println!("  ESC: Exit");
+    if let Some(path) = &options.record_path {
+        println!("Recording to {}", path);
+    }
     println!("\nPress any key to start...");
 
+    set_raw_mode(true);
     let mut input = [0];
-    io::stdin().read(&mut input).unwrap();
+    let _ = io::stdin().read(&mut input);
+    let keys = spawn_input_thread();
 
     // 端末を raw モードにする
     print!("\x1b[?1049h"); // 代替スクリーンバッファを使用
@@ -85,31 +473,223 @@ fn main() {
     let mut scene = Scene::new(width as f32, height as f32);
     let mut renderer = Renderer::new();
 
+    if let Some(path) = &options.record_path {
+        match Recorder::create(path) {
+            Ok(recorder) => renderer.start_recording(recorder),
+            Err(err) => {
+                restore_terminal();
+                eprintln!("Failed to create {}: {}", path, err);
+                return;
+            }
+        }
+    }
+
     // カメラの初期位置を設定
     scene.camera.set_position(Vec3::new(0.0, -1.0, 5.0));
     scene.camera.look_at(Vec3::new(0.0, 0.0, 0.0));
 
-    // サンプルメッシュを追加
-    let mut cube = Mesh::create_cube(1.0);
-    cube.transform.position = Vec3::new(0., 0.0, 0.0);
-    scene.add_mesh(cube);
+    renderer.render_mode = options.render_mode;
+    renderer.wireframe.hidden_line = options.hidden_line;
+    renderer.wireframe.feature_edges_only = options.feature_edges;
+    renderer.wireframe.anti_aliased = options.anti_aliased;
+    renderer.wireframe.light_markers = options.show_lights;
+    renderer.antialiasing = options.antialiasing;
+    if options.depth_cue {
+        renderer.wireframe.depth_cue = Some(DepthCue::default());
+    }
+    scene.fog = options.fog.map(Fog::new);
 
-    let mut pyramid = Mesh::create_pyramid(1.);
-    pyramid.transform.position = Vec3::new(1.5, 0., 0.0);
-    scene.add_mesh(pyramid);
+    if options.gradient {
+        scene.background = Background::Gradient {
+            top: Color::new(40, 60, 120),
+            bottom: Color::new(200, 170, 140),
+        };
+        scene.background_color = Color::new(120, 115, 130);
+    }
+    if let Some(dir) = &options.skybox_dir {
+        match load_skybox(dir) {
+            Ok(cube_map) => scene.background = Background::Skybox(Rc::new(cube_map)),
+            Err(err) => eprintln!("Failed to load skybox from {}: {}", dir, err),
+        }
+    }
+    if options.oit {
+        renderer.transparency = Transparency::WeightedBlended;
+    }
+    renderer.post_effects = std::mem::take(&mut options.post_effects);
+    if options.ssao {
+        renderer.ssao = Some(SsaoSettings::default());
+    }
+    if options.shadows {
+        // 影を落とすときは同じ向きの平行光源にして、原点のまわりを正射影で覆う
+        let projection = ShadowProjection::Orthographic {
+            half_size: 5.0,
+            near: 0.1,
+            far: 20.0,
+        };
+        scene.add_light(
+            Light::directional(Vec3::new(-2.0, -3.0, -4.0), Color::WHITE, 1.0)
+                .with_shadow(ShadowSettings::new(projection, Vec3::new(0.0, 0.0, 0.0))),
+        );
+    } else {
+        scene.add_light(Light::new(Vec3::new(2.0, 3.0, 4.0), Color::WHITE, 1.0));
+    }
+    if options.colored_lights {
+        // 上から青いスポットライト、左奥に減衰する赤い点光源
+        let mut spot = Light::spot(
+            Vec3::new(0.0, 5.0, 0.0),
+            Vec3::new(0.0, -1.0, 0.0),
+            20.0_f32.to_radians(),
+            30.0_f32.to_radians(),
+            Color::new(80, 120, 255),
+            1.0,
+        );
+        if options.shadows {
+            let projection = ShadowProjection::Perspective {
+                fov: 70.0_f32.to_radians(),
+                near: 0.5,
+                far: 10.0,
+            };
+            spot = spot.with_shadow(ShadowSettings::new(projection, Vec3::new(0.0, 0.0, 0.0)));
+        }
+        scene.add_light(spot);
+        scene.add_light(
+            Light::new(Vec3::new(-3.0, 1.0, -1.0), Color::new(255, 60, 40), 1.5)
+                .with_attenuation(Attenuation::new(1.0, 0.2, 0.1)),
+        );
+    }
+
+    if let Some(path) = &options.obj_path {
+        let mut model = match Mesh::from_obj_file(path) {
+            Ok(model) => model,
+            Err(err) => {
+                restore_terminal();
+                eprintln!("Failed to load {}: {}", path, err);
+                return;
+            }
+        };
 
-    // let mut test = Mesh::from_obj_file("african_head.obj").unwrap();
-    // test.transform.position = Vec3::new(0.0, 0.0, 0.0);
-    // test.transform.rotation = Vec3::new(0.0, 0.0, 0.0);
-    // scene.add_mesh(test);
+        // 指定がなければ african_head.obj と同じ場所の african_head_diffuse.tga を使う
+        let texture_path = options.texture_path.clone().or_else(|| {
+            let path = Path::new(path);
+            let stem = path.file_stem()?.to_str()?;
+            let diffuse = path.with_file_name(format!("{}_diffuse.tga", stem));
+            diffuse.exists().then(|| diffuse.to_string_lossy().into_owned())
+        });
+        if let Some(texture_path) = texture_path {
+            match Texture::load(&texture_path) {
+                Ok(texture) => {
+                    model.set_texture(texture);
+                    model.sampler = options.sampler(model.sampler);
+                }
+                Err(err) => eprintln!("Failed to load {}: {}", texture_path, err),
+            }
+        }
+
+        scene.add_node(Node::new("model").with_mesh(model));
+    } else {
+        // サンプルメッシュを追加
+        let mut cube = Node::new("cube").with_mesh(Mesh::create_cube(1.0));
+        cube.transform.position = Vec3::new(0., 0.0, 0.0);
+        scene.add_node(cube);
+
+        let mut pyramid = Node::new("pyramid").with_mesh(Mesh::create_pyramid(1.));
+        pyramid.transform.position = Vec3::new(1.5, 0., 0.0);
+        let pyramid = scene.add_node(pyramid);
+
+        // ピラミッドの子として自分で光る小さな立方体を置く (親の回転と上下の動きについて回る)
+        let mut light_cube = Mesh::create_cube(0.3);
+        light_cube.set_material(Material::new().with_emissive(Color::new(255, 180, 60)));
+        let mut satellite = Node::new("satellite").with_mesh(light_cube);
+        satellite.transform.position = Vec3::new(0.9, 0.0, 0.0);
+        scene.add_child(pyramid, satellite);
+    }
+
+    // 最初に足したノード (モデルかキューブ)
+    let main_node = scene.nodes.roots()[0];
+
+    if options.pbr {
+        // 最初のメッシュは金の金属、ほかはつやのある樹脂
+        for (id, node) in scene.nodes.iter_mut() {
+            let Some(mesh) = &mut node.mesh else {
+                continue;
+            };
+            let emissive = mesh.material.emissive;
+            if id == main_node {
+                mesh.set_material(Material::physically_based(Color::new(255, 200, 120), 1.0, 0.3));
+            } else {
+                mesh.set_material(
+                    Material::physically_based(Color::WHITE, 0.0, 0.4).with_emissive(emissive),
+                );
+            }
+        }
+    }
+
+    if options.glass {
+        // 最初のメッシュを半透明にする
+        if let Some(mesh) = &mut scene.nodes[main_node].mesh {
+            mesh.blend_mode = options.blend_mode;
+            mesh.opacity = 0.5;
+        }
+    }
+
+    if options.floor {
+        // つやのない市松模様か格子模様の床 (遠くでちらつかないようにトライリニアで参照する)
+        let mut floor = Mesh::create_plane(8.0);
+        let dark = Color::new(40, 40, 40);
+        floor.set_texture(if options.grid {
+            Texture::grid(256, 16, 3, Color::WHITE, dark)
+        } else {
+            Texture::checker(256, 16, Color::WHITE, dark)
+        });
+        floor.sampler = options.sampler(Sampler::trilinear(WrapMode::Repeat));
+        floor.set_material(Material::blinn_phong(Color::WHITE, 0.05, 8.0));
+        floor.transform.position = Vec3::new(0.0, -1.5, 0.0);
+        scene.add_mesh(floor);
+    }
 
     canvas.init();
     // thread::sleep(Duration::from_secs(3)); // 約60FPS
 
     let mut last_time = Instant::now();
     let mut rotation_time = 0.0f32;
+    let pyramid = scene.find("pyramid");
+    let satellite = scene.find("satellite");
+    // 衛星は 2 つの傾きの間を行き来する
+    let tilts = [
+        Quat::from_axis_angle(Vec3::new(1.0, 0.0, 0.0), -0.6),
+        Quat::from_axis_angle(Vec3::new(0.0, 0.0, 1.0), 0.6),
+    ];
+    let mut frames = 0;
+
+    'render: loop {
+        for key in keys.try_iter().flat_map(|bytes| parse_keys(&bytes)) {
+            let camera = &mut scene.camera;
+            let step = 0.2;
+            let angle = 3.0_f32.to_radians();
+            match key {
+                Key::Char(b'w') => camera.move_forward(step),
+                Key::Char(b's') => camera.move_forward(-step),
+                Key::Char(b'a') => camera.move_right(-step),
+                Key::Char(b'd') => camera.move_right(step),
+                Key::Char(b'q') => camera.move_up(step),
+                Key::Char(b'e') => camera.move_up(-step),
+                Key::Char(b'z') => camera.roll(-angle),
+                Key::Char(b'x') => camera.roll(angle),
+                Key::Char(b'c') => camera.clear_orientation(),
+                Key::Char(b'+') => camera.set_fov((camera.fov.to_degrees() - 5.0).max(20.0)),
+                Key::Char(b'-') => camera.set_fov((camera.fov.to_degrees() + 5.0).min(100.0)),
+                Key::Char(b'r') => renderer.toggle_render_mode(),
+                Key::Char(b'f') => renderer.toggle_fps_display(),
+                Key::Up => camera.rotate(0.0, angle),
+                Key::Down => camera.rotate(0.0, -angle),
+                Key::Left => camera.rotate(-angle, 0.0),
+                Key::Right => camera.rotate(angle, 0.0),
+                Key::Escape => break 'render,
+                Key::Char(_) => {}
+            }
+        }
+
 
-    loop {
         let current_time = Instant::now();
         let delta_time = current_time.duration_since(last_time).as_secs_f32();
         last_time = current_time;
@@ -117,27 +697,42 @@ fn main() {
         rotation_time += delta_time;
 
         // オブジェクトのアニメーション
-        if let Some(cube) = scene.meshes.get_mut(0) {
-            cube.transform.rotation.x = rotation_time * 0.5;
-            cube.transform.rotation.y = rotation_time * 0.3;
-        }
+        let transform = &mut scene.nodes[main_node].transform;
+        transform.set_euler(Vec3::new(rotation_time * 0.5, rotation_time * 0.3, 0.0));
 
-        if let Some(pyramid) = scene.meshes.get_mut(1) {
-            pyramid.transform.rotation.y = rotation_time * 0.8;
-            pyramid.transform.position.y = (rotation_time * 2.0).sin() * 0.5;
+        if let Some(pyramid) = pyramid {
+            let transform = &mut scene.nodes[pyramid].transform;
+            transform.set_rotation_y(rotation_time * 0.8);
+            transform.position.y = (rotation_time * 2.0).sin() * 0.5;
+        }
+        if let Some(satellite) = satellite {
+            let t = ((rotation_time * 3.0).sin() + 1.0) * 0.5;
+            scene.nodes[satellite].transform.rotation = tilts[0].slerp(&tilts[1], t);
         }
 
-        // if let Some(pyramid) = scene.meshes.get_mut(0) {
-        //     pyramid.transform.rotation.y = rotation_time * 0.8;
-        //     pyramid.transform.position.y = (rotation_time * 2.0).sin() * 0.5;
-        // }
-
-        // レンダリング
-        renderer.render(&mut canvas, &scene);
+        // レンダリング (--normals なら法線を色にして塗る)
+        if options.normals {
+            renderer.render_with_shader(&mut canvas, &scene, &NormalShader);
+        } else {
+            renderer.render(&mut canvas, &scene);
+        }
 
         // フレームレート制限
         thread::sleep(Duration::from_millis(33)); // 約60FPS
+
+        // 録画に失敗したらすぐ抜けてエラーを表示する
+        frames += 1;
+        if options.max_frames.is_some_and(|max| frames >= max) || renderer.recording_failed() {
+            break;
+        }
     }
 
-    thread::sleep(Duration::from_secs(3)); // 約60FPS
+    let result = renderer.stop_recording();
+
+    restore_terminal();
+    println!("Last FPS: {:.1}", renderer.get_fps());
+
+    if let Err(err) = result {
+        eprintln!("Recording failed: {}", err);
+    }
 }
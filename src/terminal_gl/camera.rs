@@ -1,4 +1,4 @@
-use crate::geometry::Vec3;
+use crate::geometry::{Quat, Vec3};
 use crate::matrix::Mat4;
 
 pub struct Camera {
@@ -17,6 +17,10 @@ pub struct Camera {
     // Camera angles for FPS-style movement
     pub yaw: f32,
     pub pitch: f32,
+
+    // Some のときは yaw / pitch の代わりにこの向きで forward・up・right を決める。
+    // 回転していない向きは -Z を見て +Y が上
+    pub orientation: Option<Quat>,
 }
 
 impl Camera {
@@ -35,6 +39,7 @@ impl Camera {
 
             yaw: -90.0_f32.to_radians(), // Point towards -Z initially
             pitch: 0.0,
+            orientation: None,
         };
 
         camera.update_vectors();
@@ -50,62 +55,59 @@ impl Camera {
         self.target = target;
 
         // Calculate yaw and pitch from position to target
-        let direction = Vec3::new(
-            target.x - self.position.x,
-            target.y - self.position.y,
-            target.z - self.position.z,
-        )
-        .normalize();
+        let direction = (target - self.position).normalize();
 
         self.yaw = direction.z.atan2(direction.x);
         self.pitch = direction.y.asin();
+        if self.orientation.is_some() {
+            self.orientation = Some(yaw_pitch_orientation(self.yaw, self.pitch));
+        }
 
         self.update_vectors();
     }
 
-    pub fn move_forward(&mut self, distance: f32) {
-        let forward_movement = Vec3::new(
-            self.forward.x * distance,
-            self.forward.y * distance,
-            self.forward.z * distance,
-        );
+    // クォータニオンで向きを決める (以降の rotate もクォータニオンに掛ける)
+    pub fn set_orientation(&mut self, orientation: Quat) {
+        self.orientation = Some(orientation.normalize());
+        self.update_vectors();
+        self.update_target();
+    }
 
-        self.position.x += forward_movement.x;
-        self.position.y += forward_movement.y;
-        self.position.z += forward_movement.z;
+    // 今の向きから yaw / pitch を求め直して FPS 式の回転に戻す (ロールは失われる)
+    pub fn clear_orientation(&mut self) {
+        if self.orientation.take().is_some() {
+            self.yaw = self.forward.z.atan2(self.forward.x);
+            self.pitch = self.forward.y.clamp(-1.0, 1.0).asin();
+            self.update_vectors();
+            self.update_target();
+        }
+    }
 
+    pub fn move_forward(&mut self, distance: f32) {
+        self.position += self.forward * distance;
         self.update_target();
     }
 
     pub fn move_right(&mut self, distance: f32) {
-        let right_movement = Vec3::new(
-            self.right.x * distance,
-            self.right.y * distance,
-            self.right.z * distance,
-        );
-
-        self.position.x += right_movement.x;
-        self.position.y += right_movement.y;
-        self.position.z += right_movement.z;
-
+        self.position += self.right * distance;
         self.update_target();
     }
 
     pub fn move_up(&mut self, distance: f32) {
-        let up_movement = Vec3::new(
-            self.up.x * distance,
-            self.up.y * distance,
-            self.up.z * distance,
-        );
-
-        self.position.x += up_movement.x;
-        self.position.y += up_movement.y;
-        self.position.z += up_movement.z;
-
+        self.position += self.up * distance;
         self.update_target();
     }
 
     pub fn rotate(&mut self, yaw_delta: f32, pitch_delta: f32) {
+        if let Some(orientation) = self.orientation {
+            // ヨーはワールドの上向き、ピッチはカメラの右向きのまわりに回す。
+            // yaw と同じく正の値で右を向くよう Y 軸まわりは負の向きに回す
+            let yaw = Quat::from_axis_angle(Vec3::new(0.0, 1.0, 0.0), -yaw_delta);
+            let pitch = Quat::from_axis_angle(Vec3::new(1.0, 0.0, 0.0), pitch_delta);
+            self.set_orientation(yaw.multiply(&orientation).multiply(&pitch));
+            return;
+        }
+
         self.yaw += yaw_delta;
         self.pitch += pitch_delta;
 
@@ -118,12 +120,17 @@ impl Camera {
         self.update_target();
     }
 
-    pub fn set_fov(&mut self, fov_degrees: f32) {
-        self.fov = fov_degrees.to_radians();
+    // 視線のまわりに傾ける。yaw / pitch では表せないのでクォータニオンの向きに切り替える
+    pub fn roll(&mut self, angle: f32) {
+        let orientation = self
+            .orientation
+            .unwrap_or_else(|| yaw_pitch_orientation(self.yaw, self.pitch));
+        let roll = Quat::from_axis_angle(Vec3::new(0.0, 0.0, -1.0), angle);
+        self.set_orientation(orientation.multiply(&roll));
     }
 
-    pub fn set_aspect_ratio(&mut self, width: f32, height: f32) {
-        self.aspect_ratio = width / height;
+    pub fn set_fov(&mut self, fov_degrees: f32) {
+        self.fov = fov_degrees.to_radians();
     }
 
     pub fn get_view_matrix(&self) -> Mat4 {
@@ -140,7 +147,22 @@ impl Camera {
         projection.multiply(&view)
     }
 
+    // 画面上の NDC 座標 (-1..1, y が上) を通る視線の向き (正規化済み)
+    pub fn ray_direction(&self, ndc_x: f32, ndc_y: f32) -> Vec3 {
+        let half_height = (self.fov * 0.5).tan();
+        let half_width = half_height * self.aspect_ratio;
+        (self.forward + self.right * (ndc_x * half_width) + self.up * (ndc_y * half_height))
+            .normalize()
+    }
+
     fn update_vectors(&mut self) {
+        if let Some(orientation) = self.orientation {
+            self.forward = orientation.rotate_vector(Vec3::new(0.0, 0.0, -1.0)).normalize();
+            self.right = orientation.rotate_vector(Vec3::new(1.0, 0.0, 0.0)).normalize();
+            self.up = orientation.rotate_vector(Vec3::new(0.0, 1.0, 0.0)).normalize();
+            return;
+        }
+
         // Calculate the new forward vector
         self.forward = Vec3::new(
             self.yaw.cos() * self.pitch.cos(),
@@ -158,10 +180,13 @@ impl Camera {
     }
 
     fn update_target(&mut self) {
-        self.target = Vec3::new(
-            self.position.x + self.forward.x,
-            self.position.y + self.forward.y,
-            self.position.z + self.forward.z,
-        );
+        self.target = self.position + self.forward;
     }
 }
+
+// yaw / pitch と同じ向きのクォータニオン (yaw = -90° で -Z を向く)
+fn yaw_pitch_orientation(yaw: f32, pitch: f32) -> Quat {
+    let yaw = Quat::from_axis_angle(Vec3::new(0.0, 1.0, 0.0), -(yaw + 90.0_f32.to_radians()));
+    let pitch = Quat::from_axis_angle(Vec3::new(1.0, 0.0, 0.0), pitch);
+    yaw.multiply(&pitch)
+}
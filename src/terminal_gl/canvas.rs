@@ -1,11 +1,6 @@
+use std::fmt::Write as _;
 use std::io::{self, Write};
 
-#[derive(Clone, Copy, Debug)]
-pub struct Coord {
-    pub x: i32,
-    pub y: i32,
-}
-
 #[derive(Clone, Copy, Debug)]
 pub struct ColoredCoord {
     pub x: i32,
@@ -15,13 +10,53 @@ pub struct ColoredCoord {
     pub b: u8,
 }
 
+// 端末に表示済みの内容と一致しないことを示す値
+const INVALID: u32 = u32::MAX;
+
+// ピクセルを書き込むときの今の色との合成方法
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BlendMode {
+    // 上書き
+    Opaque,
+    // src * a + dst * (1 - a)
+    Alpha,
+    // dst + src * a (明るくなるだけ。光や炎向け)
+    Additive,
+    // dst * src (a で効き具合を弱める。色ガラスや影向け)
+    Multiply,
+}
+
+// 高解像度のキャンバスを縮小するときのフィルタ
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DownsampleFilter {
+    // factor x factor ピクセルの単純平均
+    Box,
+    // 半径 factor ピクセルの三角形の重み (隣のブロックと少し混ざり、縁がより滑らか)
+    Tent,
+}
+
 pub struct Canvas {
     pub width: usize,
     pub height: usize,
     pub r: Vec<u8>,
     pub g: Vec<u8>,
     pub b: Vec<u8>,
-    pub changed_coords: Vec<Coord>,
+    // [0, 1] の深度 (小さいほど手前)
+    pub depth: Vec<f32>,
+    // MSAA のピクセルあたりサンプル数 (1 なら無効) と、サンプルごとの深度・色
+    samples: usize,
+    sample_depth: Vec<f32>,
+    sample_color: Vec<(u8, u8, u8)>,
+    // weighted blended OIT の蓄積 (重み付きの rgb * a, a の和) と revealage (1 - a の積)。
+    // 初めて使うときに確保する
+    accumulation: Vec<[f32; 4]>,
+    revealage: Vec<f32>,
+    has_transparency: bool,
+    // 端末に表示されている各セルの (上, 下) ピクセル
+    front: Vec<(u32, u32)>,
+    clear_screen: bool,
+    overlay: String,
+    frame: String,
 }
 
 impl Canvas {
@@ -32,48 +67,40 @@ impl Canvas {
             r: vec![0; width * height],
             g: vec![0; width * height],
             b: vec![0; width * height],
-            changed_coords: Vec::with_capacity(2000),
+            depth: vec![f32::INFINITY; width * height],
+            samples: 1,
+            sample_depth: Vec::new(),
+            sample_color: Vec::new(),
+            accumulation: Vec::new(),
+            revealage: Vec::new(),
+            has_transparency: false,
+            front: vec![(INVALID, INVALID); width * height.div_ceil(2)],
+            clear_screen: false,
+            overlay: String::new(),
+            frame: String::with_capacity(64 * 1024),
         }
     }
 
+    pub fn rows(&self) -> usize {
+        self.height.div_ceil(2)
+    }
+
     pub fn init(&mut self) {
         self.r.fill(0);
         self.g.fill(0);
         self.b.fill(0);
-        self.changed_coords.clear();
         self.set_black();
     }
 
     pub fn set_black(&mut self) {
-        print!("\x1b[2J\x1b[H");
-
-        let rows = (self.height + 1) / 2;
-        for row in 0..rows {
-            for col in 0..self.width {
-                let y_upper = row * 2;
-                let y_lower = row * 2 + 1;
-
-                let (ru, gu, bu) = if y_upper < self.height {
-                    let idx = y_upper * self.width + col;
-                    (self.r[idx], self.g[idx], self.b[idx])
-                } else {
-                    (0, 0, 0)
-                };
-
-                let (rl, gl, bl) = if y_lower < self.height {
-                    let idx = y_lower * self.width + col;
-                    (self.r[idx], self.g[idx], self.b[idx])
-                } else {
-                    (0, 0, 0)
-                };
+        self.invalidate();
+        self.present();
+    }
 
-                print!(
-                    "\x1b[38;2;{};{};{}m\x1b[48;2;{};{};{}m▀",
-                    ru, gu, bu, rl, gl, bl
-                );
-            }
-            println!("\x1b[0m");
-        }
+    // 次の present で画面全体を描き直す
+    pub fn invalidate(&mut self) {
+        self.front.fill((INVALID, INVALID));
+        self.clear_screen = true;
     }
 
     pub fn set_pixel(&mut self, x: i32, y: i32, r: u8, g: u8, b: u8) {
@@ -85,8 +112,29 @@ impl Canvas {
         self.r[idx] = r;
         self.g[idx] = g;
         self.b[idx] = b;
+    }
+
+    // alpha (0..1) と mode に従って今の色と合成する
+    pub fn blend_pixel(&mut self, x: i32, y: i32, rgb: (u8, u8, u8), alpha: f32, mode: BlendMode) {
+        if x < 0 || x >= self.width as i32 || y < 0 || y >= self.height as i32 {
+            return;
+        }
 
-        self.changed_coords.push(Coord { x, y });
+        let idx = (y as usize) * self.width + (x as usize);
+        let a = alpha.clamp(0.0, 1.0);
+        let blend = |dst: u8, src: u8| -> u8 {
+            let (dst, src) = (dst as f32, src as f32);
+            let value = match mode {
+                BlendMode::Opaque => src,
+                BlendMode::Alpha => src * a + dst * (1.0 - a),
+                BlendMode::Additive => dst + src * a,
+                BlendMode::Multiply => dst * (1.0 - a + a * src / 255.0),
+            };
+            value.round().clamp(0.0, 255.0) as u8
+        };
+        self.r[idx] = blend(self.r[idx], rgb.0);
+        self.g[idx] = blend(self.g[idx], rgb.1);
+        self.b[idx] = blend(self.b[idx], rgb.2);
     }
 
     pub fn set_pixels(&mut self, pixels: &mut Vec<ColoredCoord>) {
@@ -95,55 +143,398 @@ impl Canvas {
         }
     }
 
-    pub fn clear(&mut self) {
-        for coord in &self.changed_coords {
-            if coord.x < 0
-                || coord.x >= self.width as i32
-                || coord.y < 0
-                || coord.y >= self.height as i32
-            {
+    // 色を (r, g, b) で塗りつぶし、深度などのバッファを初期化する
+    pub fn clear_with(&mut self, r: u8, g: u8, b: u8) {
+        self.r.fill(r);
+        self.g.fill(g);
+        self.b.fill(b);
+        self.depth.fill(f32::INFINITY);
+        self.sample_depth.fill(f32::INFINITY);
+        if self.has_transparency {
+            self.accumulation.fill([0.0; 4]);
+            self.revealage.fill(1.0);
+            self.has_transparency = false;
+        }
+    }
+
+    // 半透明のフラグメントを順序に関係なく足し込む (weighted blended OIT)。
+    // distance は視点からの距離で、手前のものほど重くする
+    pub fn accumulate_transparent(
+        &mut self,
+        x: i32,
+        y: i32,
+        rgb: (u8, u8, u8),
+        alpha: f32,
+        distance: f32,
+    ) {
+        if x < 0 || x >= self.width as i32 || y < 0 || y >= self.height as i32 {
+            return;
+        }
+        if self.accumulation.is_empty() {
+            self.accumulation = vec![[0.0; 4]; self.width * self.height];
+            self.revealage = vec![1.0; self.width * self.height];
+        }
+
+        let a = alpha.clamp(0.0, 1.0);
+        // McGuire & Bavoil (2013) の距離による重み
+        let weight = a * (10.0 / (1e-5 + (distance / 5.0).powi(2) + (distance / 200.0).powi(6)))
+            .clamp(1e-2, 3e3);
+
+        let idx = (y as usize) * self.width + (x as usize);
+        let sum = &mut self.accumulation[idx];
+        sum[0] += rgb.0 as f32 * weight;
+        sum[1] += rgb.1 as f32 * weight;
+        sum[2] += rgb.2 as f32 * weight;
+        sum[3] += weight;
+        self.revealage[idx] *= 1.0 - a;
+        self.has_transparency = true;
+    }
+
+    // 蓄積した半透明の色を平均して、revealage に応じて今の色と混ぜる
+    pub fn resolve_transparency(&mut self) {
+        if !self.has_transparency {
+            return;
+        }
+
+        for (idx, (sum, &revealage)) in self.accumulation.iter().zip(&self.revealage).enumerate() {
+            if sum[3] <= 0.0 {
                 continue;
             }
-            let idx = (coord.y as usize) * self.width + (coord.x as usize);
-            self.r[idx] = 0;
-            self.g[idx] = 0;
-            self.b[idx] = 0;
+            let coverage = 1.0 - revealage;
+            let blend = |dst: u8, value: f32| -> u8 {
+                let average = value / sum[3];
+                (average * coverage + dst as f32 * revealage).round().clamp(0.0, 255.0) as u8
+            };
+            self.r[idx] = blend(self.r[idx], sum[0]);
+            self.g[idx] = blend(self.g[idx], sum[1]);
+            self.b[idx] = blend(self.b[idx], sum[2]);
         }
     }
 
-    pub fn present(&mut self) {
-        for coord in &self.changed_coords {
-            let x = coord.x;
-            let y = coord.y;
-
-            if x >= 0 && x < self.width as i32 && y >= 0 && y < self.height as i32 {
-                let row = y / 2;
-                let y_upper = row * 2;
-                let y_lower = row * 2 + 1;
-
-                let (ru, gu, bu) = if y_upper >= 0 && y_upper < self.height as i32 {
-                    let idx = (y_upper as usize) * self.width + (x as usize);
-                    (self.r[idx], self.g[idx], self.b[idx])
-                } else {
-                    (0, 0, 0)
-                };
+    // MSAA のサンプル数を設定する (1 で無効)
+    pub fn set_samples(&mut self, samples: usize) {
+        let samples = samples.max(1);
+        if samples == self.samples {
+            return;
+        }
+        self.samples = samples;
+        let len = if samples > 1 { self.width * self.height * samples } else { 0 };
+        self.sample_depth = vec![f32::INFINITY; len];
+        self.sample_color = vec![(0, 0, 0); len];
+    }
+
+    pub fn samples(&self) -> usize {
+        self.samples
+    }
+
+    // サンプル単位の深度テスト (書き込まない)
+    pub fn passes_sample_depth(&self, x: i32, y: i32, sample: usize, depth: f32) -> bool {
+        if x < 0 || x >= self.width as i32 || y < 0 || y >= self.height as i32 {
+            return false;
+        }
+        let idx = ((y as usize) * self.width + (x as usize)) * self.samples + sample;
+        depth < self.sample_depth[idx]
+    }
+
+    pub fn set_sample(&mut self, x: i32, y: i32, sample: usize, depth: f32, color: (u8, u8, u8)) {
+        if x < 0 || x >= self.width as i32 || y < 0 || y >= self.height as i32 {
+            return;
+        }
+        let idx = ((y as usize) * self.width + (x as usize)) * self.samples + sample;
+        self.sample_depth[idx] = depth;
+        self.sample_color[idx] = color;
+    }
 
-                let (rl, gl, bl) = if y_lower >= 0 && y_lower < self.height as i32 {
-                    let idx = (y_lower as usize) * self.width + (x as usize);
-                    (self.r[idx], self.g[idx], self.b[idx])
+    // factor 倍の大きさの source を縮小してこのキャンバスの色にする (SSAA)
+    pub fn downsample(&mut self, source: &Canvas, factor: usize, filter: DownsampleFilter) {
+        let factor = factor.max(1);
+        // 出力ピクセル 1 列 (行) ぶんの、元のピクセルの範囲と重み
+        let weights = |size: usize, source_size: usize| -> Vec<Vec<(usize, f32)>> {
+            (0..size)
+                .map(|i| match filter {
+                    DownsampleFilter::Box => (i * factor..((i + 1) * factor).min(source_size))
+                        .map(|j| (j, 1.0))
+                        .collect(),
+                    DownsampleFilter::Tent => {
+                        let center = (i as f32 + 0.5) * factor as f32;
+                        let start = (i * factor).saturating_sub(factor);
+                        let end = ((i + 2) * factor).min(source_size);
+                        (start..end)
+                            .map(|j| {
+                                let distance = (j as f32 + 0.5 - center).abs();
+                                (j, (1.0 - distance / factor as f32).max(0.0))
+                            })
+                            .filter(|&(_, w)| w > 0.0)
+                            .collect()
+                    }
+                })
+                .collect()
+        };
+        let columns = weights(self.width, source.width);
+        let rows = weights(self.height, source.height);
+
+        for (y, row) in rows.iter().enumerate() {
+            for (x, column) in columns.iter().enumerate() {
+                let (mut r, mut g, mut b, mut total) = (0.0, 0.0, 0.0, 0.0);
+                for &(sy, wy) in row {
+                    for &(sx, wx) in column {
+                        let idx = sy * source.width + sx;
+                        let w = wx * wy;
+                        r += source.r[idx] as f32 * w;
+                        g += source.g[idx] as f32 * w;
+                        b += source.b[idx] as f32 * w;
+                        total += w;
+                    }
+                }
+                if total > 0.0 {
+                    let idx = y * self.width + x;
+                    self.r[idx] = (r / total).round() as u8;
+                    self.g[idx] = (g / total).round() as u8;
+                    self.b[idx] = (b / total).round() as u8;
+                }
+            }
+        }
+    }
+
+    // サンプルを平均してピクセルの色にする。何も描かれていないサンプルは今のピクセルの色
+    // (背景) として数え、ピクセルの深度は一番手前のサンプルにする
+    pub fn resolve_samples(&mut self) {
+        if self.samples <= 1 {
+            return;
+        }
+
+        let n = self.samples;
+        for idx in 0..self.width * self.height {
+            let samples = idx * n..(idx + 1) * n;
+            let depths = &self.sample_depth[samples.clone()];
+            if depths.iter().all(|d| d.is_infinite()) {
+                continue;
+            }
+
+            let background = (self.r[idx] as u32, self.g[idx] as u32, self.b[idx] as u32);
+            let (mut r, mut g, mut b) = (0, 0, 0);
+            for (depth, color) in depths.iter().zip(&self.sample_color[samples]) {
+                let (sr, sg, sb) = if depth.is_finite() {
+                    (color.0 as u32, color.1 as u32, color.2 as u32)
                 } else {
-                    (0, 0, 0)
+                    background
                 };
+                r += sr;
+                g += sg;
+                b += sb;
+            }
+
+            let n = n as u32;
+            self.r[idx] = ((r + n / 2) / n) as u8;
+            self.g[idx] = ((g + n / 2) / n) as u8;
+            self.b[idx] = ((b + n / 2) / n) as u8;
+            let nearest = depths.iter().copied().fold(f32::INFINITY, f32::min);
+            self.depth[idx] = self.depth[idx].min(nearest);
+        }
+    }
+
+    // 手前にあれば深度を更新して true を返す
+    pub fn depth_test(&mut self, x: i32, y: i32, depth: f32) -> bool {
+        if x < 0 || x >= self.width as i32 || y < 0 || y >= self.height as i32 {
+            return false;
+        }
+
+        let idx = (y as usize) * self.width + (x as usize);
+        if depth < self.depth[idx] {
+            self.depth[idx] = depth;
+            true
+        } else {
+            false
+        }
+    }
+
+    // 深度を書き込まずに判定だけ行う
+    pub fn passes_depth(&self, x: i32, y: i32, depth: f32) -> bool {
+        if x < 0 || x >= self.width as i32 || y < 0 || y >= self.height as i32 {
+            return false;
+        }
+        depth < self.depth[(y as usize) * self.width + (x as usize)]
+    }
+
+    pub fn set_depth(&mut self, x: i32, y: i32, depth: f32) {
+        if x < 0 || x >= self.width as i32 || y < 0 || y >= self.height as i32 {
+            return;
+        }
+        self.depth[(y as usize) * self.width + (x as usize)] = depth;
+    }
+
+    // ピクセルの上に重ねるテキスト (セル単位の座標)
+    pub fn draw_text(&mut self, col: usize, row: usize, text: &str, r: u8, g: u8, b: u8) {
+        let _ = write!(
+            self.overlay,
+            "\x1b[{};{}H\x1b[38;2;{};{};{}m{}\x1b[0m",
+            row + 1,
+            col + 1,
+            r,
+            g,
+            b,
+            text
+        );
+
+        // テキストで隠れたセルは次のフレームで描き直す
+        if row < self.rows() {
+            let start = row * self.width + col.min(self.width);
+            let end = row * self.width + (col + text.chars().count()).min(self.width);
+            self.front[start..end].fill((INVALID, INVALID));
+        }
+    }
+
+    fn packed(&self, x: usize, y: usize) -> u32 {
+        if y >= self.height {
+            return 0;
+        }
+        let idx = y * self.width + x;
+        (self.r[idx] as u32) << 16 | (self.g[idx] as u32) << 8 | self.b[idx] as u32
+    }
+
+    // 前回の present から変化したセルだけを出力する
+    pub fn present(&mut self) {
+        self.frame.clear();
+        if self.clear_screen {
+            self.frame.push_str("\x1b[2J\x1b[H");
+            self.clear_screen = false;
+        }
+
+        for row in 0..self.rows() {
+            let mut cursor_in_place = false;
+            for col in 0..self.width {
+                let upper = self.packed(col, row * 2);
+                let lower = self.packed(col, row * 2 + 1);
+
+                let cell = &mut self.front[row * self.width + col];
+                if *cell == (upper, lower) {
+                    cursor_in_place = false;
+                    continue;
+                }
+                *cell = (upper, lower);
 
-                print!("\x1b[{};{}H", row + 1, x + 1);
-                print!(
+                if !cursor_in_place {
+                    let _ = write!(self.frame, "\x1b[{};{}H", row + 1, col + 1);
+                    cursor_in_place = true;
+                }
+                let _ = write!(
+                    self.frame,
                     "\x1b[38;2;{};{};{}m\x1b[48;2;{};{};{}m▀",
-                    ru, gu, bu, rl, gl, bl
+                    upper >> 16,
+                    (upper >> 8) & 0xff,
+                    upper & 0xff,
+                    lower >> 16,
+                    (lower >> 8) & 0xff,
+                    lower & 0xff
                 );
             }
         }
 
-        print!("\x1b[0m");
-        io::stdout().flush().unwrap();
+        self.frame.push_str("\x1b[0m");
+        self.frame.push_str(&self.overlay);
+        self.overlay.clear();
+
+        let mut stdout = io::stdout().lock();
+        stdout.write_all(self.frame.as_bytes()).unwrap();
+        stdout.flush().unwrap();
+    }
+
+    // 直前の present で端末に書き出したエスケープシーケンス
+    pub fn last_frame(&self) -> &str {
+        &self.frame
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn msaa_resolve_averages_covered_samples_with_background() {
+        let mut canvas = Canvas::new(4, 1);
+        canvas.clear_with(0, 0, 100);
+        canvas.set_samples(4);
+        // 縁のピクセルでは 4 サンプル中 n 個だけ三角形に覆われる
+        for (x, covered) in [(0, 0), (1, 1), (2, 2), (3, 4)] {
+            for sample in 0..covered {
+                canvas.set_sample(x, 0, sample, 0.5 - sample as f32 * 0.1, (200, 40, 0));
+            }
+        }
+        canvas.resolve_samples();
+
+        let pixel = |x: usize| (canvas.r[x], canvas.g[x], canvas.b[x]);
+        assert_eq!(pixel(0), (0, 0, 100));
+        assert_eq!(pixel(1), (50, 10, 75));
+        assert_eq!(pixel(2), (100, 20, 50));
+        assert_eq!(pixel(3), (200, 40, 0));
+        // 深度は一番手前のサンプル
+        assert!(canvas.depth[0].is_infinite());
+        assert_eq!(canvas.depth[2], 0.4);
+        assert_eq!(canvas.depth[3], 0.5 - 3.0 * 0.1);
+    }
+
+    #[test]
+    fn box_and_tent_downsample_weights() {
+        // 左半分が白、右半分が黒の 4x4 を 2x2 に縮小する
+        let mut source = Canvas::new(4, 4);
+        for y in 0..4 {
+            for x in 0..2 {
+                source.set_pixel(x, y, 255, 255, 255);
+            }
+        }
+
+        let mut boxed = Canvas::new(2, 2);
+        boxed.downsample(&source, 2, DownsampleFilter::Box);
+        assert_eq!(boxed.r, [255, 0, 255, 0]);
+
+        // Tent の重みは中心から 0.5 離れた 2 ピクセルが 0.75、1.5 離れた隣のブロックの 1 ピクセルが 0.25。
+        // 左: 255 * 1.5 / 1.75、右: 255 * 0.25 / 1.75
+        let mut tent = Canvas::new(2, 2);
+        tent.downsample(&source, 2, DownsampleFilter::Tent);
+        assert_eq!(tent.r, [219, 36, 219, 36]);
+        assert_eq!(tent.r, tent.g);
+    }
+
+    #[test]
+    fn blend_modes_combine_with_destination() {
+        let blend = |mode: BlendMode, alpha: f32| {
+            let mut canvas = Canvas::new(1, 1);
+            canvas.clear_with(100, 100, 100);
+            canvas.blend_pixel(0, 0, (200, 50, 0), alpha, mode);
+            (canvas.r[0], canvas.g[0], canvas.b[0])
+        };
+        assert_eq!(blend(BlendMode::Opaque, 0.5), (200, 50, 0));
+        assert_eq!(blend(BlendMode::Alpha, 0.5), (150, 75, 50));
+        assert_eq!(blend(BlendMode::Alpha, 0.0), (100, 100, 100));
+        assert_eq!(blend(BlendMode::Additive, 0.5), (200, 125, 100));
+        assert_eq!(blend(BlendMode::Additive, 1.0), (255, 150, 100));
+        // 100 * (0.5 + 0.5 * src / 255)
+        assert_eq!(blend(BlendMode::Multiply, 0.5), (89, 60, 50));
+        assert_eq!(blend(BlendMode::Multiply, 1.0), (78, 20, 0));
+        // alpha は 0..1 に切り詰める
+        assert_eq!(blend(BlendMode::Alpha, 2.0), (200, 50, 0));
+    }
+
+    #[test]
+    fn oit_result_does_not_depend_on_draw_order() {
+        let red = ((255, 0, 0), 0.5, 2.0);
+        let blue = ((0, 0, 255), 0.5, 4.0);
+        let composite = |layers: [((u8, u8, u8), f32, f32); 2]| {
+            let mut canvas = Canvas::new(1, 1);
+            canvas.clear_with(0, 200, 0);
+            for (rgb, alpha, distance) in layers {
+                canvas.accumulate_transparent(0, 0, rgb, alpha, distance);
+            }
+            canvas.resolve_transparency();
+            (canvas.r[0], canvas.g[0], canvas.b[0])
+        };
+
+        let front_to_back = composite([red, blue]);
+        assert_eq!(front_to_back, composite([blue, red]));
+        // 背景は (1 - 0.5)^2 だけ見え、手前の赤の方が重い
+        let (r, g, b) = front_to_back;
+        assert_eq!(g, 50);
+        assert!(r > b && b > 0);
+        assert_eq!(r as u32 + b as u32, 191);
     }
 }
@@ -0,0 +1,92 @@
+use crate::geometry::Vec3;
+use crate::mesh::Mesh;
+use std::collections::HashMap;
+
+// メッシュの辺と、それを共有する面 (三角形) の情報
+#[derive(Clone, Copy, Debug)]
+pub struct MeshEdge {
+    pub vertices: [usize; 2],
+    pub faces: (usize, Option<usize>),
+    // 両側の面の法線がなす角 (ラジアン)。境界辺では 0
+    pub dihedral: f32,
+}
+
+fn face_normal(mesh: &Mesh, face: usize) -> Vec3 {
+    let [a, b, c] = mesh.triangles[face].vertices.map(|i| mesh.vertices[i].position);
+    (b - a).cross(&(c - a)).normalize()
+}
+
+// 位置が同じ頂点は UV や法線が違っても同じ点として辺をつなぐ
+fn weld_key(position: Vec3) -> (u32, u32, u32) {
+    // -0.0 と 0.0 を同じ値にそろえる
+    (
+        (position.x + 0.0).to_bits(),
+        (position.y + 0.0).to_bits(),
+        (position.z + 0.0).to_bits(),
+    )
+}
+
+pub fn find_edges(mesh: &Mesh) -> Vec<MeshEdge> {
+    let mut welded: HashMap<(u32, u32, u32), usize> = HashMap::new();
+    let canonical: Vec<usize> = mesh
+        .vertices
+        .iter()
+        .enumerate()
+        .map(|(i, v)| *welded.entry(weld_key(v.position)).or_insert(i))
+        .collect();
+
+    let mut edges: Vec<MeshEdge> = Vec::new();
+    let mut lookup: HashMap<(usize, usize), usize> = HashMap::new();
+
+    for (face, triangle) in mesh.triangles.iter().enumerate() {
+        for k in 0..3 {
+            let a = triangle.vertices[k];
+            let b = triangle.vertices[(k + 1) % 3];
+            let (ca, cb) = (canonical[a], canonical[b]);
+            if ca == cb {
+                continue;
+            }
+            let key = (ca.min(cb), ca.max(cb));
+
+            match lookup.get(&key) {
+                Some(&index) => {
+                    // 3 枚以上の面が接する辺は最初の 2 枚だけを見る
+                    if edges[index].faces.1.is_none() {
+                        edges[index].faces.1 = Some(face);
+                    }
+                }
+                None => {
+                    lookup.insert(key, edges.len());
+                    edges.push(MeshEdge {
+                        vertices: [a, b],
+                        faces: (face, None),
+                        dihedral: 0.0,
+                    });
+                }
+            }
+        }
+    }
+
+    for edge in edges.iter_mut() {
+        if let Some(other) = edge.faces.1 {
+            let n0 = face_normal(mesh, edge.faces.0);
+            let n1 = face_normal(mesh, other);
+            edge.dihedral = n0.dot(&n1).clamp(-1.0, 1.0).acos();
+        }
+    }
+
+    edges
+}
+
+// 描画する辺の判定。front_facing は面ごとの表裏
+// シルエット: 表と裏の境目 / 折り目: 表の面同士で角度が crease_angle を超える / 境界: 片側しか面がない
+pub fn is_feature_edge(edge: &MeshEdge, front_facing: &[bool], crease_angle: f32) -> bool {
+    let front0 = front_facing[edge.faces.0];
+    match edge.faces.1 {
+        None => front0,
+        Some(other) => {
+            let front1 = front_facing[other];
+            front0 != front1 || (front0 && edge.dihedral > crease_angle)
+        }
+    }
+}
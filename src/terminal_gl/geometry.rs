@@ -1,18 +1,32 @@
-use crate::terminal_gl::{Canvas, ColoredCoord};
-
-#[derive(Clone, Copy, Debug)]
+use crate::matrix::Mat4;
+use crate::raster::{ScreenVertex, rasterize_line_aa};
+use crate::terminal_gl::{BlendMode, Canvas, ColoredCoord};
+use std::ops::{
+    Add, AddAssign, Div, DivAssign, Index, IndexMut, Mul, MulAssign, Neg, Sub, SubAssign,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub struct Vec2 {
     pub x: f32,
     pub y: f32,
 }
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub struct Vec3 {
     pub x: f32,
     pub y: f32,
     pub z: f32,
 }
 
+// 同次座標 (クリップ座標など)
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Vec4 {
+    pub x: f32,
+    pub y: f32,
+    pub z: f32,
+    pub w: f32,
+}
+
 #[derive(Clone, Copy, Debug)]
 pub struct Color {
     pub r: u8,
@@ -79,8 +93,321 @@ impl Vec3 {
             z: self.x * other.y - self.y * other.x,
         }
     }
+
+    // w を付けて同次座標にする (点なら 1、向きなら 0)
+    pub fn extend(&self, w: f32) -> Vec4 {
+        Vec4::new(self.x, self.y, self.z, w)
+    }
+}
+
+impl Vec4 {
+    pub fn new(x: f32, y: f32, z: f32, w: f32) -> Self {
+        Self { x, y, z, w }
+    }
+
+    pub fn xyz(&self) -> Vec3 {
+        Vec3::new(self.x, self.y, self.z)
+    }
+
+    pub fn length(&self) -> f32 {
+        self.dot(self).sqrt()
+    }
+
+    pub fn normalize(&self) -> Self {
+        let len = self.length();
+        if len == 0.0 { *self } else { *self / len }
+    }
+
+    pub fn dot(&self, other: &Vec4) -> f32 {
+        self.x * other.x + self.y * other.y + self.z * other.z + self.w * other.w
+    }
+}
+
+impl From<[f32; 4]> for Vec4 {
+    fn from(v: [f32; 4]) -> Self {
+        Self::new(v[0], v[1], v[2], v[3])
+    }
+}
+
+impl From<Vec4> for [f32; 4] {
+    fn from(v: Vec4) -> Self {
+        [v.x, v.y, v.z, v.w]
+    }
+}
+
+// 回転を表す単位クォータニオン (x, y, z がベクトル部、w がスカラー部)
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Quat {
+    pub x: f32,
+    pub y: f32,
+    pub z: f32,
+    pub w: f32,
 }
 
+impl Quat {
+    pub const IDENTITY: Quat = Quat {
+        x: 0.0,
+        y: 0.0,
+        z: 0.0,
+        w: 1.0,
+    };
+
+    pub fn new(x: f32, y: f32, z: f32, w: f32) -> Self {
+        Self { x, y, z, w }
+    }
+
+    // axis まわりに angle (ラジアン) 回す
+    pub fn from_axis_angle(axis: Vec3, angle: f32) -> Self {
+        let axis = axis.normalize();
+        let (sin, cos) = (angle * 0.5).sin_cos();
+        Self::new(axis.x * sin, axis.y * sin, axis.z * sin, cos)
+    }
+
+    // X, Y, Z 軸まわりの角度から。Transform の以前の行列と同じく Z·Y·X の順に回す
+    pub fn from_euler(euler: Vec3) -> Self {
+        let x = Self::from_axis_angle(Vec3::new(1.0, 0.0, 0.0), euler.x);
+        let y = Self::from_axis_angle(Vec3::new(0.0, 1.0, 0.0), euler.y);
+        let z = Self::from_axis_angle(Vec3::new(0.0, 0.0, 1.0), euler.z);
+        z.multiply(&y.multiply(&x))
+    }
+
+    // from_euler の逆。Y が ±90° のときは X と Z を区別できないので Z を 0 にする
+    pub fn to_euler(self) -> Vec3 {
+        let m = self.to_matrix().m;
+        let y = (-m[2][0]).clamp(-1.0, 1.0).asin();
+        if m[2][0].abs() < 0.9999 {
+            Vec3::new(m[2][1].atan2(m[2][2]), y, m[1][0].atan2(m[0][0]))
+        } else {
+            Vec3::new((-m[1][2]).atan2(m[1][1]), y, 0.0)
+        }
+    }
+
+    // self · other (other の回転のあとに self の回転)
+    pub fn multiply(&self, other: &Quat) -> Quat {
+        Quat {
+            x: self.w * other.x + self.x * other.w + self.y * other.z - self.z * other.y,
+            y: self.w * other.y - self.x * other.z + self.y * other.w + self.z * other.x,
+            z: self.w * other.z + self.x * other.y - self.y * other.x + self.z * other.w,
+            w: self.w * other.w - self.x * other.x - self.y * other.y - self.z * other.z,
+        }
+    }
+
+    pub fn dot(&self, other: &Quat) -> f32 {
+        self.x * other.x + self.y * other.y + self.z * other.z + self.w * other.w
+    }
+
+    pub fn length(&self) -> f32 {
+        self.dot(self).sqrt()
+    }
+
+    pub fn normalize(&self) -> Self {
+        let len = self.length();
+        if len == 0.0 {
+            Self::IDENTITY
+        } else {
+            Self::new(self.x / len, self.y / len, self.z / len, self.w / len)
+        }
+    }
+
+    pub fn rotate_vector(&self, v: Vec3) -> Vec3 {
+        // v + 2w(q×v) + 2q×(q×v)
+        let q = Vec3::new(self.x, self.y, self.z);
+        let t = q.cross(&v) * 2.0;
+        v + t * self.w + q.cross(&t)
+    }
+
+    // 球面線形補間。近い方の向きを回って t = 0 で self、1 で other になる
+    pub fn slerp(&self, other: &Quat, t: f32) -> Quat {
+        let mut other = *other;
+        let mut cos = self.dot(&other);
+        if cos < 0.0 {
+            other = Quat::new(-other.x, -other.y, -other.z, -other.w);
+            cos = -cos;
+        }
+
+        // ほぼ同じ向きでは sin が 0 に近く不安定なので線形補間で代用する
+        let (a, b) = if cos > 0.9995 {
+            (1.0 - t, t)
+        } else {
+            let angle = cos.acos();
+            let sin = angle.sin();
+            (((1.0 - t) * angle).sin() / sin, (t * angle).sin() / sin)
+        };
+        Quat::new(
+            self.x * a + other.x * b,
+            self.y * a + other.y * b,
+            self.z * a + other.z * b,
+            self.w * a + other.w * b,
+        )
+        .normalize()
+    }
+
+    pub fn to_matrix(self) -> Mat4 {
+        let Quat { x, y, z, w } = self.normalize();
+        let mut mat = Mat4::identity();
+        mat.m[0][0] = 1.0 - 2.0 * (y * y + z * z);
+        mat.m[0][1] = 2.0 * (x * y - w * z);
+        mat.m[0][2] = 2.0 * (x * z + w * y);
+        mat.m[1][0] = 2.0 * (x * y + w * z);
+        mat.m[1][1] = 1.0 - 2.0 * (x * x + z * z);
+        mat.m[1][2] = 2.0 * (y * z - w * x);
+        mat.m[2][0] = 2.0 * (x * z - w * y);
+        mat.m[2][1] = 2.0 * (y * z + w * x);
+        mat.m[2][2] = 1.0 - 2.0 * (x * x + y * y);
+        mat
+    }
+}
+
+impl Default for Quat {
+    fn default() -> Self {
+        Self::IDENTITY
+    }
+}
+
+// 成分ごとの演算子と、各ベクトル型に共通の演算を実装する
+macro_rules! impl_vector {
+    ($name:ident { $($index:literal => $field:ident),+ }) => {
+        impl $name {
+            // t = 0 で self、1 で other
+            pub fn lerp(&self, other: &$name, t: f32) -> $name {
+                *self + (*other - *self) * t
+            }
+
+            pub fn distance(&self, other: &$name) -> f32 {
+                (*other - *self).length()
+            }
+
+            // normal (正規化済み) を法線とする面で反射した向き
+            pub fn reflect(&self, normal: &$name) -> $name {
+                *self - *normal * (2.0 * self.dot(normal))
+            }
+
+            pub fn min(&self, other: &$name) -> $name {
+                $name { $($field: self.$field.min(other.$field)),+ }
+            }
+
+            pub fn max(&self, other: &$name) -> $name {
+                $name { $($field: self.$field.max(other.$field)),+ }
+            }
+
+            pub fn abs(&self) -> $name {
+                $name { $($field: self.$field.abs()),+ }
+            }
+
+            // すべての成分の差が epsilon 以下
+            pub fn approx_eq(&self, other: &$name, epsilon: f32) -> bool {
+                $((self.$field - other.$field).abs() <= epsilon)&&+
+            }
+        }
+
+        impl Add for $name {
+            type Output = $name;
+
+            fn add(self, other: $name) -> $name {
+                $name { $($field: self.$field + other.$field),+ }
+            }
+        }
+
+        impl Sub for $name {
+            type Output = $name;
+
+            fn sub(self, other: $name) -> $name {
+                $name { $($field: self.$field - other.$field),+ }
+            }
+        }
+
+        // 成分ごとの積
+        impl Mul for $name {
+            type Output = $name;
+
+            fn mul(self, other: $name) -> $name {
+                $name { $($field: self.$field * other.$field),+ }
+            }
+        }
+
+        impl Mul<f32> for $name {
+            type Output = $name;
+
+            fn mul(self, scalar: f32) -> $name {
+                $name { $($field: self.$field * scalar),+ }
+            }
+        }
+
+        impl Mul<$name> for f32 {
+            type Output = $name;
+
+            fn mul(self, vector: $name) -> $name {
+                vector * self
+            }
+        }
+
+        impl Div<f32> for $name {
+            type Output = $name;
+
+            fn div(self, scalar: f32) -> $name {
+                $name { $($field: self.$field / scalar),+ }
+            }
+        }
+
+        impl Neg for $name {
+            type Output = $name;
+
+            fn neg(self) -> $name {
+                $name { $($field: -self.$field),+ }
+            }
+        }
+
+        impl AddAssign for $name {
+            fn add_assign(&mut self, other: $name) {
+                $(self.$field += other.$field;)+
+            }
+        }
+
+        impl SubAssign for $name {
+            fn sub_assign(&mut self, other: $name) {
+                $(self.$field -= other.$field;)+
+            }
+        }
+
+        impl MulAssign<f32> for $name {
+            fn mul_assign(&mut self, scalar: f32) {
+                $(self.$field *= scalar;)+
+            }
+        }
+
+        impl DivAssign<f32> for $name {
+            fn div_assign(&mut self, scalar: f32) {
+                $(self.$field /= scalar;)+
+            }
+        }
+
+        // v[0] が x、v[1] が y ...
+        impl Index<usize> for $name {
+            type Output = f32;
+
+            fn index(&self, index: usize) -> &f32 {
+                match index {
+                    $($index => &self.$field,)+
+                    _ => panic!("{} has no component {}", stringify!($name), index),
+                }
+            }
+        }
+
+        impl IndexMut<usize> for $name {
+            fn index_mut(&mut self, index: usize) -> &mut f32 {
+                match index {
+                    $($index => &mut self.$field,)+
+                    _ => panic!("{} has no component {}", stringify!($name), index),
+                }
+            }
+        }
+    };
+}
+
+impl_vector!(Vec2 { 0 => x, 1 => y });
+impl_vector!(Vec3 { 0 => x, 1 => y, 2 => z });
+impl_vector!(Vec4 { 0 => x, 1 => y, 2 => z, 3 => w });
+
 impl Color {
     pub const WHITE: Color = Color {
         r: 255,
@@ -122,9 +449,36 @@ impl Color {
 
     pub fn multiply(&self, factor: f32) -> Color {
         Color {
-            r: ((self.r as f32 * factor).min(255.0).max(0.0)) as u8,
-            g: ((self.g as f32 * factor).min(255.0).max(0.0)) as u8,
-            b: ((self.b as f32 * factor).min(255.0).max(0.0)) as u8,
+            r: (self.r as f32 * factor).clamp(0.0, 255.0) as u8,
+            g: (self.g as f32 * factor).clamp(0.0, 255.0) as u8,
+            b: (self.b as f32 * factor).clamp(0.0, 255.0) as u8,
+        }
+    }
+
+    // RGB ごとに倍率をかける (x, y, z が r, g, b の倍率)
+    pub fn multiply_rgb(&self, factors: Vec3) -> Color {
+        Color {
+            r: (self.r as f32 * factors.x).clamp(0.0, 255.0) as u8,
+            g: (self.g as f32 * factors.y).clamp(0.0, 255.0) as u8,
+            b: (self.b as f32 * factors.z).clamp(0.0, 255.0) as u8,
+        }
+    }
+
+    // 成分ごとの和 (255 で飽和)
+    pub fn saturating_add(&self, other: &Color) -> Color {
+        Color {
+            r: self.r.saturating_add(other.r),
+            g: self.g.saturating_add(other.g),
+            b: self.b.saturating_add(other.b),
+        }
+    }
+
+    // 成分ごとの積 (テクスチャ色と頂点色の合成など)
+    pub fn modulate(&self, other: &Color) -> Color {
+        Color {
+            r: ((self.r as u32 * other.r as u32 + 127) / 255) as u8,
+            g: ((self.g as u32 * other.g as u32 + 127) / 255) as u8,
+            b: ((self.b as u32 * other.b as u32 + 127) / 255) as u8,
         }
     }
 }
@@ -232,69 +586,30 @@ pub fn draw_triangle_wireframe(
     );
 }
 
-// 塗りつぶし三角形（シンプルな実装）
-pub fn draw_triangle_filled(
-    mut p0: Vec2,
-    mut p1: Vec2,
-    mut p2: Vec2,
-    canvas: &Canvas,
-    color: Color,
-    pixels: &mut Vec<ColoredCoord>,
-) {
-    // Y座標でソート
-    if p0.y > p1.y {
-        std::mem::swap(&mut p0, &mut p1);
-    }
-    if p1.y > p2.y {
-        std::mem::swap(&mut p1, &mut p2);
-    }
-    if p0.y > p1.y {
-        std::mem::swap(&mut p0, &mut p1);
-    }
-
-    let total_height = p2.y - p0.y;
-    if total_height < 0.1 {
-        return;
-    }
-
-    for y in (p0.y as i32)..=(p2.y as i32) {
-        let second_half = y as f32 > p1.y || p1.y == p0.y;
-        let segment_height = if second_half {
-            p2.y - p1.y
-        } else {
-            p1.y - p0.y
-        };
-
-        if segment_height < 0.1 {
-            continue;
-        }
-
-        let alpha = (y as f32 - p0.y) / total_height;
-        let beta = (y as f32 - if second_half { p1.y } else { p0.y }) / segment_height;
-
-        let mut a = p0.x + (p2.x - p0.x) * alpha;
-        let mut b = if second_half {
-            p1.x + (p2.x - p1.x) * beta
-        } else {
-            p0.x + (p1.x - p0.x) * beta
-        };
+// 被覆率 coverage に応じてキャンバスの今の色と混ぜる。
+// 同じピクセルに何度か重なると (線の交点など) 被覆がそのぶん積み重なるよう、その場で書き込む
+pub fn blend_pixel(x: i32, y: i32, coverage: f32, canvas: &mut Canvas, color: Color) {
+    canvas.blend_pixel(x, y, (color.r, color.g, color.b), coverage, BlendMode::Alpha);
+}
 
-        if a > b {
-            std::mem::swap(&mut a, &mut b);
-        }
+// アンチエイリアス線 (Xiaolin Wu)
+pub fn draw_line_aa(p0: Vec2, p1: Vec2, canvas: &mut Canvas, color: Color) {
+    let a = ScreenVertex {
+        position: p0,
+        depth: 0.0,
+        inv_w: 1.0,
+    };
+    let b = ScreenVertex { position: p1, ..a };
+    let (width, height) = (canvas.width, canvas.height);
+    rasterize_line_aa(a, b, width, height, |fragment| {
+        blend_pixel(fragment.x, fragment.y, fragment.coverage, canvas, color);
+    });
+}
 
-        for x in (a as i32)..=(b as i32) {
-            if x >= 0 && x < canvas.width as i32 && y >= 0 && y < canvas.height as i32 {
-                pixels.push(ColoredCoord {
-                    x,
-                    y,
-                    r: color.r,
-                    g: color.g,
-                    b: color.b,
-                });
-            }
-        }
-    }
+pub fn draw_triangle_wireframe_aa(p0: Vec2, p1: Vec2, p2: Vec2, canvas: &mut Canvas, color: Color) {
+    draw_line_aa(p0, p1, canvas, color);
+    draw_line_aa(p1, p2, canvas, color);
+    draw_line_aa(p2, p0, canvas, color);
 }
 
 // 円描画
@@ -349,3 +664,306 @@ pub fn draw_circle(
         plot_circle_points(x, y, center_x, center_y, pixels, color);
     }
 }
+
+// アンチエイリアス円 (Wu)。1/8 円ごとに内側と外側の 2 ピクセルへ被覆率を振り分ける
+pub fn draw_circle_aa(
+    center_x: i32,
+    center_y: i32,
+    radius: f32,
+    canvas: &mut Canvas,
+    color: Color,
+) {
+    let end = (radius / std::f32::consts::SQRT_2).ceil() as i32;
+    for x in 0..=end {
+        let y = (radius * radius - (x * x) as f32).max(0.0).sqrt();
+        let inner = y.floor() as i32;
+        let fraction = y - y.floor();
+        if inner < x {
+            break;
+        }
+
+        for (minor, coverage) in [(inner, 1.0 - fraction), (inner + 1, fraction)] {
+            let mut points = vec![
+                (x, minor),
+                (-x, minor),
+                (x, -minor),
+                (-x, -minor),
+                (minor, x),
+                (-minor, x),
+                (minor, -x),
+                (-minor, -x),
+            ];
+            // 軸上と対角線上では同じ点が重なるので、被覆が二重にならないよう 1 回だけ混ぜる
+            points.sort_unstable();
+            points.dedup();
+            for (dx, dy) in points {
+                blend_pixel(center_x + dx, center_y + dy, coverage, canvas, color);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EPSILON: f32 = 1e-4;
+
+    // 同じ回転を表すなら q と -q のどちらでもよい
+    fn same_rotation(a: Quat, b: Quat) -> bool {
+        a.dot(&b).abs() > 1.0 - EPSILON
+    }
+
+    fn sample_eulers() -> Vec<Vec3> {
+        let angles = [-2.9, -1.2, -0.4, 0.0, 0.3, 1.1, 2.5];
+        let mut eulers = Vec::new();
+        for &x in &angles {
+            for &y in &[-1.3, -0.7, 0.0, 0.5, 1.4] {
+                for &z in &angles {
+                    eulers.push(Vec3::new(x, y, z));
+                }
+            }
+        }
+        eulers
+    }
+
+    #[test]
+    fn aa_coverage_accumulates_on_overlap() {
+        let mut canvas = Canvas::new(4, 4);
+        let white = Color::new(255, 255, 255);
+        let pixel = |c: &Canvas| (c.r[5], c.g[5], c.b[5]);
+        blend_pixel(1, 1, 0.5, &mut canvas, white);
+        assert_eq!(pixel(&canvas), (128, 128, 128));
+        blend_pixel(1, 1, 0.5, &mut canvas, white);
+        assert_eq!(pixel(&canvas), (192, 192, 192));
+    }
+
+    #[test]
+    fn aa_lines_blend_over_each_other() {
+        // 同じ線を 2 回描くと部分的に覆われたピクセルは明るくなり、完全に覆われたピクセルは変わらない
+        let mut once = Canvas::new(16, 16);
+        let color = Color::new(200, 100, 50);
+        let (p0, p1) = (Vec2::new(1.0, 2.0), Vec2::new(14.0, 7.3));
+        draw_line_aa(p0, p1, &mut once, color);
+        let mut twice = Canvas::new(16, 16);
+        draw_line_aa(p0, p1, &mut twice, color);
+        draw_line_aa(p0, p1, &mut twice, color);
+
+        let mut partial = 0;
+        for i in 0..16 * 16 {
+            assert!(twice.r[i] >= once.r[i]);
+            assert!(twice.r[i] <= color.r);
+            if once.r[i] > 0 && once.r[i] < color.r - 1 {
+                assert!(twice.r[i] > once.r[i]);
+                partial += 1;
+            }
+        }
+        assert!(partial > 0);
+    }
+
+    #[test]
+    fn aa_circle_splits_coverage_between_inner_and_outer_pixels() {
+        let white = Color::new(255, 255, 255);
+        let at = |c: &Canvas, x: usize, y: usize| c.r[y * c.width + x];
+
+        // 半径が整数なら軸上のピクセルは完全に覆われ、その外側は覆われない
+        let mut canvas = Canvas::new(24, 24);
+        draw_circle_aa(10, 10, 5.0, &mut canvas, white);
+        for (x, y) in [(15, 10), (5, 10), (10, 15), (10, 5)] {
+            assert_eq!(at(&canvas, x, y), 255);
+        }
+        assert_eq!(at(&canvas, 16, 10), 0);
+        assert_eq!(at(&canvas, 10, 10), 0);
+
+        // 半径 5.5 なら軸上の内側と外側に半分ずつ
+        let mut canvas = Canvas::new(24, 24);
+        draw_circle_aa(10, 10, 5.5, &mut canvas, white);
+        for (x, y) in [(15, 10), (16, 10), (10, 4), (10, 5)] {
+            assert_eq!(at(&canvas, x, y), 128);
+        }
+        // x = 3 では y = √(5.5² - 3²) ≈ 4.61 なので内側に 0.39、外側に 0.61
+        let y = (5.5f32 * 5.5 - 9.0).sqrt();
+        let inner = (255.0 * (1.0 - y.fract())).round() as u8;
+        let outer = (255.0 * y.fract()).round() as u8;
+        assert!(at(&canvas, 13, 14).abs_diff(inner) <= 1);
+        assert!(at(&canvas, 13, 15).abs_diff(outer) <= 1);
+        assert!(at(&canvas, 14, 13).abs_diff(inner) <= 1);
+        assert_eq!(at(&canvas, 10, 10), 0);
+    }
+
+    #[test]
+    fn quat_euler_round_trip() {
+        for euler in sample_eulers() {
+            let q = Quat::from_euler(euler);
+            assert!(euler.approx_eq(&q.to_euler(), EPSILON), "{euler:?}");
+            assert!(same_rotation(Quat::from_euler(q.to_euler()), q), "{euler:?}");
+        }
+    }
+
+    #[test]
+    fn quat_euler_round_trip_at_gimbal_lock() {
+        // Y が ±90° のときは角度は一意でないが、同じ回転に戻る
+        for y in [std::f32::consts::FRAC_PI_2, -std::f32::consts::FRAC_PI_2] {
+            let q = Quat::from_euler(Vec3::new(0.7, y, -0.3));
+            assert!(same_rotation(Quat::from_euler(q.to_euler()), q));
+        }
+    }
+
+    #[test]
+    fn quat_matrix_matches_zyx_euler_matrix() {
+        for euler in sample_eulers() {
+            let expected = Mat4::rotation_z(euler.z)
+                .multiply(&Mat4::rotation_y(euler.y))
+                .multiply(&Mat4::rotation_x(euler.x));
+            assert!(
+                Quat::from_euler(euler).to_matrix().approx_eq(&expected, EPSILON),
+                "{euler:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn quat_rotate_vector_matches_matrix() {
+        let v = Vec3::new(0.3, -1.2, 2.0);
+        for euler in sample_eulers() {
+            let q = Quat::from_euler(euler);
+            assert!(q.rotate_vector(v).approx_eq(&q.to_matrix().transform_vector(v), EPSILON));
+        }
+    }
+
+    #[test]
+    fn quat_slerp_endpoints_and_midpoint() {
+        let a = Quat::from_euler(Vec3::new(0.2, -0.5, 1.0));
+        let b = Quat::from_euler(Vec3::new(-1.0, 0.8, 0.3));
+        assert!(same_rotation(a.slerp(&b, 0.0), a));
+        assert!(same_rotation(a.slerp(&b, 1.0), b));
+
+        // 符号が逆の other でも近い方を回るので、端点は同じ回転になる
+        let neg_b = Quat::new(-b.x, -b.y, -b.z, -b.w);
+        assert!(same_rotation(a.slerp(&neg_b, 1.0), b));
+
+        // 軸まわりの回転なら中間は角度の半分
+        let axis = Vec3::new(0.0, 1.0, 0.0);
+        let start = Quat::from_axis_angle(axis, 0.2);
+        let end = Quat::from_axis_angle(axis, 1.4);
+        let mid = start.slerp(&end, 0.5);
+        assert!(same_rotation(mid, Quat::from_axis_angle(axis, 0.8)));
+        assert!((mid.length() - 1.0).abs() < EPSILON);
+    }
+
+    #[test]
+    fn quat_slerp_nearly_equal_rotations() {
+        let a = Quat::from_axis_angle(Vec3::new(1.0, 0.0, 0.0), 0.5);
+        let b = Quat::from_axis_angle(Vec3::new(1.0, 0.0, 0.0), 0.5001);
+        let mid = a.slerp(&b, 0.5);
+        assert!(same_rotation(mid, a));
+        assert!((mid.length() - 1.0).abs() < EPSILON);
+    }
+
+    #[test]
+    fn vector_operators_work_component_wise() {
+        let a = Vec3::new(1.0, 2.0, 3.0);
+        let b = Vec3::new(4.0, -5.0, 0.5);
+        assert_eq!(a + b, Vec3::new(5.0, -3.0, 3.5));
+        assert_eq!(a - b, Vec3::new(-3.0, 7.0, 2.5));
+        assert_eq!(a * b, Vec3::new(4.0, -10.0, 1.5));
+        assert_eq!(a * 2.0, Vec3::new(2.0, 4.0, 6.0));
+        assert_eq!(2.0 * a, a * 2.0);
+        assert_eq!(a / 2.0, Vec3::new(0.5, 1.0, 1.5));
+        assert_eq!(-a, Vec3::new(-1.0, -2.0, -3.0));
+
+        let mut c = a;
+        c += b;
+        assert_eq!(c, a + b);
+        c -= b;
+        assert_eq!(c, a);
+        c *= 3.0;
+        assert_eq!(c, a * 3.0);
+        c /= 3.0;
+        assert_eq!(c, a);
+
+        let v = Vec2::new(3.0, -4.0);
+        assert_eq!(v + Vec2::new(1.0, 1.0), Vec2::new(4.0, -3.0));
+        assert_eq!(-v * 0.5, Vec2::new(-1.5, 2.0));
+    }
+
+    #[test]
+    fn vec4_operators_and_helpers() {
+        let a = Vec4::new(1.0, 2.0, 3.0, 4.0);
+        let b = Vec4::new(0.5, 0.5, 0.5, 0.5);
+        assert_eq!(a + b, Vec4::new(1.5, 2.5, 3.5, 4.5));
+        assert_eq!(a - b * 2.0, Vec4::new(0.0, 1.0, 2.0, 3.0));
+        assert_eq!(a.dot(&b), 5.0);
+        assert_eq!(a.xyz(), Vec3::new(1.0, 2.0, 3.0));
+        assert_eq!(Vec3::new(1.0, 2.0, 3.0).extend(1.0), Vec4::new(1.0, 2.0, 3.0, 1.0));
+        assert!((Vec4::new(0.0, 3.0, 0.0, 4.0).normalize().length() - 1.0).abs() < EPSILON);
+        let array: [f32; 4] = a.into();
+        assert_eq!(array, [1.0, 2.0, 3.0, 4.0]);
+        assert_eq!(Vec4::from(array), a);
+    }
+
+    #[test]
+    fn index_reads_and_writes_components() {
+        let mut v = Vec4::new(1.0, 2.0, 3.0, 4.0);
+        assert_eq!([v[0], v[1], v[2], v[3]], [1.0, 2.0, 3.0, 4.0]);
+        v[2] = 7.0;
+        assert_eq!(v.z, 7.0);
+
+        let mut u = Vec3::new(1.0, 2.0, 3.0);
+        u[0] += 1.0;
+        assert_eq!(u, Vec3::new(2.0, 2.0, 3.0));
+        assert_eq!(Vec2::new(5.0, 6.0)[1], 6.0);
+    }
+
+    #[test]
+    #[should_panic(expected = "Vec3 has no component 3")]
+    fn index_out_of_range_panics() {
+        let _ = Vec3::new(1.0, 2.0, 3.0)[3];
+    }
+
+    #[test]
+    fn approx_eq_compares_every_component() {
+        let a = Vec3::new(1.0, 2.0, 3.0);
+        assert!(a.approx_eq(&Vec3::new(1.0005, 1.9995, 3.0), 1e-3));
+        assert!(!a.approx_eq(&Vec3::new(1.0, 2.0, 3.01), 1e-3));
+        assert!(!a.approx_eq(&Vec3::new(1.01, 2.0, 3.0), 1e-3));
+        assert!(Vec4::new(0.0, 0.0, 0.0, 1.0).approx_eq(&Vec4::new(0.0, 0.0, 0.0, 1.0), 0.0));
+    }
+
+    #[test]
+    fn reflect_mirrors_across_the_normal() {
+        let up = Vec3::new(0.0, 1.0, 0.0);
+        let incoming = Vec3::new(1.0, -1.0, 0.0);
+        assert_eq!(incoming.reflect(&up), Vec3::new(1.0, 1.0, 0.0));
+        // 面に沿った向きは変わらず、法線の向きは反転する
+        assert_eq!(Vec3::new(0.0, 0.0, 2.0).reflect(&up), Vec3::new(0.0, 0.0, 2.0));
+        assert_eq!(up.reflect(&up), -up);
+        assert_eq!(Vec2::new(3.0, -2.0).reflect(&Vec2::new(0.0, 1.0)), Vec2::new(3.0, 2.0));
+    }
+
+    #[test]
+    fn lerp_interpolates_between_endpoints() {
+        let a = Vec3::new(0.0, 10.0, -2.0);
+        let b = Vec3::new(4.0, 20.0, 2.0);
+        assert_eq!(a.lerp(&b, 0.0), a);
+        assert_eq!(a.lerp(&b, 1.0), b);
+        assert_eq!(a.lerp(&b, 0.25), Vec3::new(1.0, 12.5, -1.0));
+        assert_eq!(Vec2::new(0.0, 0.0).lerp(&Vec2::new(2.0, 4.0), 0.5), Vec2::new(1.0, 2.0));
+    }
+
+    #[test]
+    fn mat4_times_vec4_transforms_points_and_directions() {
+        let translation = Mat4::translation(1.0, 2.0, 3.0);
+        let point = Vec4::new(1.0, 1.0, 1.0, 1.0);
+        let direction = Vec4::new(1.0, 1.0, 1.0, 0.0);
+        assert_eq!(translation * point, Vec4::new(2.0, 3.0, 4.0, 1.0));
+        assert_eq!(translation * direction, direction);
+
+        // transform_point と同じ結果になる
+        let m = Quat::from_euler(Vec3::new(0.3, -0.7, 1.1)).to_matrix()
+            * Mat4::scale(2.0, 0.5, 1.5)
+            * translation;
+        let p = Vec3::new(-0.4, 2.2, 0.9);
+        assert!((m * p.extend(1.0)).xyz().approx_eq(&m.transform_point(p), EPSILON));
+    }
+}
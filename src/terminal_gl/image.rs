@@ -0,0 +1,786 @@
+use crate::geometry::Color;
+use crate::texture::Texture;
+use std::io::{self, ErrorKind};
+
+// 外部クレートを使わない画像デコーダ (PPM / TGA / PNG)
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(ErrorKind::InvalidData, message.to_string())
+}
+
+// 先頭のマジックバイトで形式を判定する
+pub fn decode(data: &[u8]) -> io::Result<Texture> {
+    if data.starts_with(b"\x89PNG\r\n\x1a\n") {
+        decode_png(data)
+    } else if data.starts_with(b"P3") || data.starts_with(b"P6") {
+        decode_ppm(data)
+    } else {
+        // TGA にはマジックバイトがないので最後に試す
+        decode_tga(data)
+    }
+}
+
+// ---- PPM (P3 / P6) ----
+
+pub fn decode_ppm(data: &[u8]) -> io::Result<Texture> {
+    let mut pos = 0;
+
+    // 空白とコメントを飛ばして次のトークンを読む
+    let next_token = |pos: &mut usize| -> io::Result<String> {
+        loop {
+            while *pos < data.len() && data[*pos].is_ascii_whitespace() {
+                *pos += 1;
+            }
+            if *pos < data.len() && data[*pos] == b'#' {
+                while *pos < data.len() && data[*pos] != b'\n' {
+                    *pos += 1;
+                }
+                continue;
+            }
+            break;
+        }
+        let start = *pos;
+        while *pos < data.len() && !data[*pos].is_ascii_whitespace() {
+            *pos += 1;
+        }
+        if start == *pos {
+            return Err(invalid("unexpected end of PPM data"));
+        }
+        Ok(String::from_utf8_lossy(&data[start..*pos]).into_owned())
+    };
+
+    let magic = next_token(&mut pos)?;
+    let parse = |token: String| {
+        token
+            .parse::<usize>()
+            .map_err(|_| invalid("invalid number in PPM header"))
+    };
+    let width = parse(next_token(&mut pos)?)?;
+    let height = parse(next_token(&mut pos)?)?;
+    let max_value = parse(next_token(&mut pos)?)?;
+    if max_value == 0 || max_value > 65535 {
+        return Err(invalid("invalid PPM max value"));
+    }
+    if width == 0 || height == 0 {
+        return Err(invalid("empty PPM image"));
+    }
+
+    let scale = |v: usize| (v.min(max_value) * 255 / max_value) as u8;
+    let count = width
+        .checked_mul(height)
+        .ok_or_else(|| invalid("PPM image is too large"))?;
+    // ヘッダの大きさを信用せず、データ量を超える確保はしない
+    let mut pixels = Vec::with_capacity(count.min(data.len()));
+
+    match magic.as_str() {
+        "P3" => {
+            for _ in 0..count {
+                let r = parse(next_token(&mut pos)?)?;
+                let g = parse(next_token(&mut pos)?)?;
+                let b = parse(next_token(&mut pos)?)?;
+                pixels.push(Color::new(scale(r), scale(g), scale(b)));
+            }
+        }
+        "P6" => {
+            // ヘッダの後の空白 1 バイトを飛ばす
+            pos += 1;
+            let bytes_per_sample = if max_value > 255 { 2 } else { 1 };
+            let body = data
+                .get(pos..pos + count * 3 * bytes_per_sample)
+                .ok_or_else(|| invalid("truncated PPM data"))?;
+            for px in body.chunks_exact(3 * bytes_per_sample) {
+                let sample = |i: usize| {
+                    if bytes_per_sample == 2 {
+                        (px[i * 2] as usize) << 8 | px[i * 2 + 1] as usize
+                    } else {
+                        px[i] as usize
+                    }
+                };
+                pixels.push(Color::new(scale(sample(0)), scale(sample(1)), scale(sample(2))));
+            }
+        }
+        _ => return Err(invalid("unsupported PPM format")),
+    }
+
+    Ok(Texture::from_pixels(width, height, pixels))
+}
+
+// ---- TGA (無圧縮 / RLE、トゥルーカラー / グレースケール / カラーマップ) ----
+
+pub fn decode_tga(data: &[u8]) -> io::Result<Texture> {
+    if data.len() < 18 {
+        return Err(invalid("truncated TGA header"));
+    }
+
+    let id_length = data[0] as usize;
+    let color_map_type = data[1];
+    let image_type = data[2];
+    let color_map_first = u16::from_le_bytes([data[3], data[4]]) as usize;
+    let color_map_length = u16::from_le_bytes([data[5], data[6]]) as usize;
+    let color_map_depth = data[7] as usize;
+    let width = u16::from_le_bytes([data[12], data[13]]) as usize;
+    let height = u16::from_le_bytes([data[14], data[15]]) as usize;
+    let pixel_depth = data[16] as usize;
+    let descriptor = data[17];
+
+    let rle = matches!(image_type, 9..=11);
+    if !matches!(image_type & !8, 1..=3) || color_map_type > 1 || width == 0 || height == 0 {
+        return Err(invalid("unsupported TGA image"));
+    }
+
+    // カラーマップの要素は 15 / 16 / 24 / 32 ビット、ピクセルは形式ごとに決まった深さだけ読める
+    let valid_color_depth = |depth: usize| matches!(depth, 15 | 16 | 24 | 32);
+    if color_map_type == 1 && !valid_color_depth(color_map_depth) {
+        return Err(invalid("unsupported TGA color map depth"));
+    }
+    let valid_pixel_depth = match image_type & !8 {
+        1 => color_map_type == 1 && matches!(pixel_depth, 8 | 16),
+        2 => valid_color_depth(pixel_depth),
+        _ => pixel_depth == 8,
+    };
+    if !valid_pixel_depth {
+        return Err(invalid("unsupported TGA pixel depth"));
+    }
+
+    let mut pos = 18 + id_length;
+
+    // 1 ピクセル分のバイト列を色に変換する
+    let to_color = |bytes: &[u8], depth: usize| -> Color {
+        match depth {
+            8 => Color::new(bytes[0], bytes[0], bytes[0]),
+            15 | 16 => {
+                let v = u16::from_le_bytes([bytes[0], bytes[1]]);
+                let expand = |c: u16| ((c & 0x1f) * 255 / 31) as u8;
+                Color::new(expand(v >> 10), expand(v >> 5), expand(v))
+            }
+            _ => Color::new(bytes[2], bytes[1], bytes[0]),
+        }
+    };
+
+    let mut color_map = Vec::new();
+    if color_map_type == 1 {
+        let entry_size = color_map_depth.div_ceil(8);
+        let table = data
+            .get(pos..pos + color_map_length * entry_size)
+            .ok_or_else(|| invalid("truncated TGA color map"))?;
+        color_map = table
+            .chunks_exact(entry_size)
+            .map(|entry| to_color(entry, color_map_depth))
+            .collect();
+        pos += color_map_length * entry_size;
+    }
+
+    let bytes_per_pixel = pixel_depth.div_ceil(8);
+
+    let read_pixel = |bytes: &[u8]| -> io::Result<Color> {
+        if image_type & !8 == 1 {
+            let index = if bytes_per_pixel == 2 {
+                u16::from_le_bytes([bytes[0], bytes[1]]) as usize
+            } else {
+                bytes[0] as usize
+            };
+            color_map
+                .get(index.wrapping_sub(color_map_first))
+                .copied()
+                .ok_or_else(|| invalid("TGA color index out of range"))
+        } else {
+            Ok(to_color(bytes, pixel_depth))
+        }
+    };
+
+    let count = width * height;
+    let mut pixels = Vec::with_capacity(count);
+    while pixels.len() < count {
+        if rle {
+            let header = *data.get(pos).ok_or_else(|| invalid("truncated TGA data"))?;
+            pos += 1;
+            let run = (header & 0x7f) as usize + 1;
+            if header & 0x80 != 0 {
+                let bytes = data
+                    .get(pos..pos + bytes_per_pixel)
+                    .ok_or_else(|| invalid("truncated TGA data"))?;
+                let color = read_pixel(bytes)?;
+                pos += bytes_per_pixel;
+                for _ in 0..run.min(count - pixels.len()) {
+                    pixels.push(color);
+                }
+            } else {
+                for _ in 0..run.min(count - pixels.len()) {
+                    let bytes = data
+                        .get(pos..pos + bytes_per_pixel)
+                        .ok_or_else(|| invalid("truncated TGA data"))?;
+                    pixels.push(read_pixel(bytes)?);
+                    pos += bytes_per_pixel;
+                }
+            }
+        } else {
+            let bytes = data
+                .get(pos..pos + bytes_per_pixel)
+                .ok_or_else(|| invalid("truncated TGA data"))?;
+            pixels.push(read_pixel(bytes)?);
+            pos += bytes_per_pixel;
+        }
+    }
+
+    // 既定の原点は左下なので、上から下の並びにそろえる
+    if descriptor & 0x20 == 0 {
+        for y in 0..height / 2 {
+            for x in 0..width {
+                pixels.swap(y * width + x, (height - 1 - y) * width + x);
+            }
+        }
+    }
+    if descriptor & 0x10 != 0 {
+        for row in pixels.chunks_exact_mut(width) {
+            row.reverse();
+        }
+    }
+
+    Ok(Texture::from_pixels(width, height, pixels))
+}
+
+// ---- PNG (インターレースなし、全カラータイプ) ----
+
+pub fn decode_png(data: &[u8]) -> io::Result<Texture> {
+    let mut pos = 8;
+    let mut header = None;
+    let mut palette: Vec<Color> = Vec::new();
+    let mut compressed = Vec::new();
+
+    while pos + 8 <= data.len() {
+        let length = u32::from_be_bytes([data[pos], data[pos + 1], data[pos + 2], data[pos + 3]]) as usize;
+        let kind = &data[pos + 4..pos + 8];
+        let body = data
+            .get(pos + 8..pos + 8 + length)
+            .ok_or_else(|| invalid("truncated PNG chunk"))?;
+        pos += 12 + length;
+
+        match kind {
+            b"IHDR" => {
+                if body.len() < 13 {
+                    return Err(invalid("invalid PNG header"));
+                }
+                let width = u32::from_be_bytes([body[0], body[1], body[2], body[3]]) as usize;
+                let height = u32::from_be_bytes([body[4], body[5], body[6], body[7]]) as usize;
+                if width == 0 || height == 0 {
+                    return Err(invalid("empty PNG image"));
+                }
+                header = Some((width, height, body[8] as usize, body[9], body[12]));
+            }
+            b"PLTE" => {
+                palette = body
+                    .chunks_exact(3)
+                    .map(|c| Color::new(c[0], c[1], c[2]))
+                    .collect();
+            }
+            b"IDAT" => compressed.extend_from_slice(body),
+            b"IEND" => break,
+            _ => {}
+        }
+    }
+
+    let (width, height, bit_depth, color_type, interlace) =
+        header.ok_or_else(|| invalid("missing PNG header"))?;
+    if interlace != 0 {
+        return Err(invalid("interlaced PNG is not supported"));
+    }
+
+    let channels = match color_type {
+        0 | 3 => 1,
+        2 => 3,
+        4 => 2,
+        6 => 4,
+        _ => return Err(invalid("unsupported PNG color type")),
+    };
+    let valid_bit_depth = match color_type {
+        0 => matches!(bit_depth, 1 | 2 | 4 | 8 | 16),
+        3 => matches!(bit_depth, 1 | 2 | 4 | 8),
+        _ => matches!(bit_depth, 8 | 16),
+    };
+    if !valid_bit_depth {
+        return Err(invalid("invalid PNG bit depth"));
+    }
+
+    // zlib ヘッダ (2 バイト) の後が deflate ストリーム
+    if compressed.len() < 2 {
+        return Err(invalid("missing PNG image data"));
+    }
+    let raw = inflate(&compressed[2..])?;
+
+    let bits_per_pixel = channels * bit_depth;
+    let too_large = || invalid("PNG image is too large");
+    let stride = width
+        .checked_mul(bits_per_pixel)
+        .ok_or_else(too_large)?
+        .div_ceil(8);
+    let filter_step = bits_per_pixel.div_ceil(8);
+    let raw_size = (stride + 1).checked_mul(height).ok_or_else(too_large)?;
+    if raw.len() < raw_size {
+        return Err(invalid("truncated PNG image data"));
+    }
+
+    // スキャンラインのフィルタを戻す
+    let mut image = vec![0u8; height * stride];
+    for y in 0..height {
+        let filter = raw[y * (stride + 1)];
+        let src = &raw[y * (stride + 1) + 1..(y + 1) * (stride + 1)];
+        let (done, rest) = image.split_at_mut(y * stride);
+        let previous = if y > 0 { &done[(y - 1) * stride..] } else { &[][..] };
+        let line = &mut rest[..stride];
+
+        for i in 0..stride {
+            let a = if i >= filter_step { line[i - filter_step] } else { 0 };
+            let b = if y > 0 { previous[i] } else { 0 };
+            let c = if y > 0 && i >= filter_step { previous[i - filter_step] } else { 0 };
+            let predicted = match filter {
+                0 => 0,
+                1 => a,
+                2 => b,
+                3 => ((a as u16 + b as u16) / 2) as u8,
+                4 => paeth(a, b, c),
+                _ => return Err(invalid("invalid PNG filter type")),
+            };
+            line[i] = src[i].wrapping_add(predicted);
+        }
+    }
+
+    // サンプルを 8 ビットに変換する
+    let sample = |line: &[u8], index: usize| -> u8 {
+        match bit_depth {
+            16 => line[index * 2],
+            8 => line[index],
+            _ => {
+                let bit = index * bit_depth;
+                let value = (line[bit / 8] >> (8 - bit_depth - bit % 8)) & ((1 << bit_depth) - 1);
+                if color_type == 3 {
+                    value
+                } else {
+                    (value as u32 * 255 / ((1 << bit_depth) - 1)) as u8
+                }
+            }
+        }
+    };
+
+    let mut pixels = Vec::with_capacity(width * height);
+    for line in image.chunks_exact(stride) {
+        for x in 0..width {
+            let color = match color_type {
+                0 | 4 => {
+                    let v = sample(line, x * channels);
+                    Color::new(v, v, v)
+                }
+                3 => *palette
+                    .get(sample(line, x) as usize)
+                    .ok_or_else(|| invalid("PNG palette index out of range"))?,
+                _ => Color::new(
+                    sample(line, x * channels),
+                    sample(line, x * channels + 1),
+                    sample(line, x * channels + 2),
+                ),
+            };
+            pixels.push(color);
+        }
+    }
+
+    Ok(Texture::from_pixels(width, height, pixels))
+}
+
+fn paeth(a: u8, b: u8, c: u8) -> u8 {
+    let p = a as i16 + b as i16 - c as i16;
+    let pa = (p - a as i16).abs();
+    let pb = (p - b as i16).abs();
+    let pc = (p - c as i16).abs();
+    if pa <= pb && pa <= pc {
+        a
+    } else if pb <= pc {
+        b
+    } else {
+        c
+    }
+}
+
+// ---- deflate 伸長 (RFC 1951) ----
+
+struct BitReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+    buffer: u32,
+    count: u32,
+}
+
+impl BitReader<'_> {
+    fn bits(&mut self, n: u32) -> io::Result<u32> {
+        while self.count < n {
+            let byte = *self
+                .data
+                .get(self.pos)
+                .ok_or_else(|| invalid("unexpected end of deflate stream"))?;
+            self.pos += 1;
+            self.buffer |= (byte as u32) << self.count;
+            self.count += 8;
+        }
+        let value = self.buffer & ((1u32 << n) - 1);
+        self.buffer >>= n;
+        self.count -= n;
+        Ok(value)
+    }
+
+    fn align_to_byte(&mut self) {
+        self.buffer = 0;
+        self.count = 0;
+    }
+}
+
+// 符号長から作る正規ハフマン符号
+struct Huffman {
+    counts: [u16; 16],
+    symbols: Vec<u16>,
+}
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> Self {
+        let mut counts = [0u16; 16];
+        for &len in lengths {
+            counts[len as usize] += 1;
+        }
+        counts[0] = 0;
+
+        let mut offsets = [0u16; 16];
+        for len in 1..16 {
+            offsets[len] = offsets[len - 1] + counts[len - 1];
+        }
+
+        let mut symbols = vec![0u16; lengths.len()];
+        for (symbol, &len) in lengths.iter().enumerate() {
+            if len != 0 {
+                symbols[offsets[len as usize] as usize] = symbol as u16;
+                offsets[len as usize] += 1;
+            }
+        }
+
+        Self { counts, symbols }
+    }
+
+    fn decode(&self, reader: &mut BitReader) -> io::Result<u16> {
+        let mut code: i32 = 0;
+        let mut first: i32 = 0;
+        let mut index: i32 = 0;
+        for len in 1..16 {
+            code |= reader.bits(1)? as i32;
+            let count = self.counts[len] as i32;
+            if code - first < count {
+                return Ok(self.symbols[(index + code - first) as usize]);
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        Err(invalid("invalid Huffman code"))
+    }
+}
+
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 258,
+];
+const LENGTH_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+const DISTANCE_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DISTANCE_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+
+pub fn inflate(data: &[u8]) -> io::Result<Vec<u8>> {
+    let mut reader = BitReader {
+        data,
+        pos: 0,
+        buffer: 0,
+        count: 0,
+    };
+    let mut out = Vec::with_capacity(data.len() * 4);
+
+    loop {
+        let last = reader.bits(1)? == 1;
+        match reader.bits(2)? {
+            0 => {
+                reader.align_to_byte();
+                let header = data
+                    .get(reader.pos..reader.pos + 4)
+                    .ok_or_else(|| invalid("truncated stored block"))?;
+                let len = u16::from_le_bytes([header[0], header[1]]) as usize;
+                reader.pos += 4;
+                let block = data
+                    .get(reader.pos..reader.pos + len)
+                    .ok_or_else(|| invalid("truncated stored block"))?;
+                out.extend_from_slice(block);
+                reader.pos += len;
+            }
+            1 => {
+                let mut lengths = [0u8; 288 + 30];
+                lengths[..144].fill(8);
+                lengths[144..256].fill(9);
+                lengths[256..280].fill(7);
+                lengths[280..288].fill(8);
+                lengths[288..].fill(5);
+                let literal = Huffman::new(&lengths[..288]);
+                let distance = Huffman::new(&lengths[288..]);
+                inflate_block(&mut reader, &mut out, &literal, &distance)?;
+            }
+            2 => {
+                let (literal, distance) = read_dynamic_tables(&mut reader)?;
+                inflate_block(&mut reader, &mut out, &literal, &distance)?;
+            }
+            _ => return Err(invalid("invalid deflate block type")),
+        }
+
+        if last {
+            return Ok(out);
+        }
+    }
+}
+
+fn read_dynamic_tables(reader: &mut BitReader) -> io::Result<(Huffman, Huffman)> {
+    const ORDER: [usize; 19] = [16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15];
+
+    let literal_count = reader.bits(5)? as usize + 257;
+    let distance_count = reader.bits(5)? as usize + 1;
+    let code_count = reader.bits(4)? as usize + 4;
+
+    let mut code_lengths = [0u8; 19];
+    for &index in ORDER.iter().take(code_count) {
+        code_lengths[index] = reader.bits(3)? as u8;
+    }
+    let code_huffman = Huffman::new(&code_lengths);
+
+    let mut lengths = vec![0u8; literal_count + distance_count];
+    let mut i = 0;
+    while i < lengths.len() {
+        let symbol = code_huffman.decode(reader)?;
+        let (value, repeat) = match symbol {
+            0..=15 => (symbol as u8, 1),
+            16 => {
+                let previous = *lengths
+                    .get(i.wrapping_sub(1))
+                    .ok_or_else(|| invalid("repeat with no previous length"))?;
+                (previous, 3 + reader.bits(2)? as usize)
+            }
+            17 => (0, 3 + reader.bits(3)? as usize),
+            _ => (0, 11 + reader.bits(7)? as usize),
+        };
+        if i + repeat > lengths.len() {
+            return Err(invalid("too many code lengths"));
+        }
+        lengths[i..i + repeat].fill(value);
+        i += repeat;
+    }
+
+    Ok((
+        Huffman::new(&lengths[..literal_count]),
+        Huffman::new(&lengths[literal_count..]),
+    ))
+}
+
+fn inflate_block(
+    reader: &mut BitReader,
+    out: &mut Vec<u8>,
+    literal: &Huffman,
+    distance: &Huffman,
+) -> io::Result<()> {
+    loop {
+        let symbol = literal.decode(reader)? as usize;
+        match symbol {
+            0..=255 => out.push(symbol as u8),
+            256 => return Ok(()),
+            257..=285 => {
+                let index = symbol - 257;
+                let length = LENGTH_BASE[index] as usize
+                    + reader.bits(LENGTH_EXTRA[index] as u32)? as usize;
+
+                let index = distance.decode(reader)? as usize;
+                if index >= 30 {
+                    return Err(invalid("invalid deflate distance"));
+                }
+                let dist = DISTANCE_BASE[index] as usize
+                    + reader.bits(DISTANCE_EXTRA[index] as u32)? as usize;
+                if dist > out.len() {
+                    return Err(invalid("deflate distance too far back"));
+                }
+
+                let start = out.len() - dist;
+                for k in 0..length {
+                    out.push(out[start + k]);
+                }
+            }
+            _ => return Err(invalid("invalid deflate symbol")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_invalid(result: io::Result<Texture>) {
+        match result {
+            Ok(_) => panic!("decoded an invalid image"),
+            Err(error) => assert_eq!(error.kind(), ErrorKind::InvalidData),
+        }
+    }
+
+    fn rgb(color: Color) -> (u8, u8, u8) {
+        (color.r, color.g, color.b)
+    }
+
+    // CRC は読まないので 0 を入れる
+    fn png_chunk(out: &mut Vec<u8>, kind: &[u8; 4], body: &[u8]) {
+        out.extend_from_slice(&(body.len() as u32).to_be_bytes());
+        out.extend_from_slice(kind);
+        out.extend_from_slice(body);
+        out.extend_from_slice(&[0; 4]);
+    }
+
+    // フィルタ済みのスキャンラインを無圧縮の deflate ブロックに入れた PNG
+    fn png(width: u32, height: u32, bit_depth: u8, color_type: u8, raw: &[u8]) -> Vec<u8> {
+        let mut data = b"\x89PNG\r\n\x1a\n".to_vec();
+        let mut header = Vec::new();
+        header.extend_from_slice(&width.to_be_bytes());
+        header.extend_from_slice(&height.to_be_bytes());
+        header.extend_from_slice(&[bit_depth, color_type, 0, 0, 0]);
+        png_chunk(&mut data, b"IHDR", &header);
+
+        let mut zlib = vec![0x78, 0x01, 0x01];
+        zlib.extend_from_slice(&(raw.len() as u16).to_le_bytes());
+        zlib.extend_from_slice(&(!(raw.len() as u16)).to_le_bytes());
+        zlib.extend_from_slice(raw);
+        png_chunk(&mut data, b"IDAT", &zlib);
+        png_chunk(&mut data, b"IEND", &[]);
+        data
+    }
+
+    fn tga_header(image_type: u8, width: u16, height: u16, pixel_depth: u8) -> Vec<u8> {
+        let mut data = vec![0u8; 18];
+        data[2] = image_type;
+        data[12..14].copy_from_slice(&width.to_le_bytes());
+        data[14..16].copy_from_slice(&height.to_le_bytes());
+        data[16] = pixel_depth;
+        // 原点は左上
+        data[17] = 0x20;
+        data
+    }
+
+    #[test]
+    fn ppm_decodes_pixels() {
+        let texture = decode(b"P3\n# comment\n2 1\n255\n255 0 0  0 128 255\n").unwrap();
+        assert_eq!((texture.width, texture.height), (2, 1));
+        assert_eq!(rgb(texture.texel(0, 0)), (255, 0, 0));
+        assert_eq!(rgb(texture.texel(1, 0)), (0, 128, 255));
+
+        let texture = decode(b"P6 1 1 255\n\x0a\x14\x1e").unwrap();
+        assert_eq!(rgb(texture.texel(0, 0)), (10, 20, 30));
+    }
+
+    #[test]
+    fn ppm_rejects_empty_and_oversized_images() {
+        assert_invalid(decode_ppm(b"P6 0 0 255\n"));
+        assert_invalid(decode_ppm(b"P3 0 4 255\n"));
+        assert_invalid(decode_ppm(b"P3 4 0 255\n"));
+        assert_invalid(decode_ppm(b"P3 4294967296 4294967296 255\n1 2 3\n"));
+        assert_invalid(decode_ppm(b"P6 100000 100000 255\n\x00\x00\x00"));
+    }
+
+    #[test]
+    fn png_decodes_rgb_and_low_bit_depth_gray() {
+        // 2x1 の RGB、フィルタ 0
+        let texture = decode(&png(2, 1, 8, 2, &[0, 255, 0, 0, 1, 2, 3])).unwrap();
+        assert_eq!(rgb(texture.texel(0, 0)), (255, 0, 0));
+        assert_eq!(rgb(texture.texel(1, 0)), (1, 2, 3));
+
+        // 3x1 の 1 ビットグレースケール (1, 0, 1)
+        let texture = decode(&png(3, 1, 1, 0, &[0, 0b1010_0000])).unwrap();
+        assert_eq!(rgb(texture.texel(0, 0)), (255, 255, 255));
+        assert_eq!(rgb(texture.texel(1, 0)), (0, 0, 0));
+        assert_eq!(rgb(texture.texel(2, 0)), (255, 255, 255));
+    }
+
+    #[test]
+    fn png_rejects_empty_images() {
+        assert_invalid(decode_png(&png(0, 0, 8, 2, &[])));
+        assert_invalid(decode_png(&png(0, 1, 8, 2, &[0])));
+        assert_invalid(decode_png(&png(1, 0, 8, 2, &[])));
+    }
+
+    #[test]
+    fn png_rejects_hostile_dimensions() {
+        // 行の大きさ x 高さが usize に収まらない
+        assert_invalid(decode_png(&png(u32::MAX, u32::MAX, 16, 6, &[0; 16])));
+        assert_invalid(decode_png(&png(u32::MAX, 1, 16, 6, &[0; 16])));
+        // 収まるがデータが足りない
+        assert_invalid(decode_png(&png(100_000, 100_000, 8, 2, &[0; 16])));
+    }
+
+    #[test]
+    fn png_rejects_invalid_bit_depths() {
+        let raw = [0u8; 64];
+        let cases = [(0, 0), (0, 3), (0, 32), (2, 4), (2, 0), (3, 16), (4, 1), (6, 2)];
+        for (color_type, bit_depth) in cases {
+            assert_invalid(decode_png(&png(2, 2, bit_depth, color_type, &raw)));
+        }
+    }
+
+    #[test]
+    fn tga_decodes_true_color_and_color_map() {
+        let mut data = tga_header(2, 2, 1, 24);
+        data.extend_from_slice(&[3, 2, 1, 30, 20, 10]);
+        let texture = decode(&data).unwrap();
+        assert_eq!(rgb(texture.texel(0, 0)), (1, 2, 3));
+        assert_eq!(rgb(texture.texel(1, 0)), (10, 20, 30));
+
+        // 16 ビットのカラーマップ 2 色を 8 ビットのインデックスで引く
+        let mut data = tga_header(1, 2, 1, 8);
+        data[1] = 1;
+        data[5..7].copy_from_slice(&2u16.to_le_bytes());
+        data[7] = 16;
+        data.extend_from_slice(&0x7c00u16.to_le_bytes());
+        data.extend_from_slice(&0x001fu16.to_le_bytes());
+        data.extend_from_slice(&[1, 0]);
+        let texture = decode(&data).unwrap();
+        assert_eq!(rgb(texture.texel(0, 0)), (0, 0, 255));
+        assert_eq!(rgb(texture.texel(1, 0)), (255, 0, 0));
+    }
+
+    #[test]
+    fn tga_rejects_invalid_depths() {
+        // カラーマップの深さが 0 や 15 / 16 以外で 2 バイトになるもの
+        for depth in [0, 9, 12] {
+            let mut data = tga_header(1, 1, 1, 8);
+            data[1] = 1;
+            data[5..7].copy_from_slice(&1u16.to_le_bytes());
+            data[7] = depth;
+            data.extend_from_slice(&[0, 0, 0]);
+            assert_invalid(decode_tga(&data));
+        }
+
+        let cases = [(2, 0), (2, 8), (2, 12), (2, 40), (3, 16), (3, 0), (10, 9)];
+        for (image_type, pixel_depth) in cases {
+            let mut data = tga_header(image_type, 1, 1, pixel_depth);
+            data.extend_from_slice(&[0; 8]);
+            assert_invalid(decode_tga(&data));
+        }
+
+        // カラーマップのないカラーマップ形式
+        let mut data = tga_header(1, 1, 1, 8);
+        data.push(0);
+        assert_invalid(decode_tga(&data));
+    }
+
+    #[test]
+    fn tga_rejects_empty_images() {
+        assert_invalid(decode_tga(&tga_header(2, 0, 0, 24)));
+        assert_invalid(decode_tga(&tga_header(2, 3, 0, 24)));
+    }
+}
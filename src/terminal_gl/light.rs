@@ -0,0 +1,420 @@
+use crate::geometry::{Color, Vec3};
+use crate::material::{LightingModel, Material};
+use crate::matrix::Mat4;
+use crate::shadow::ShadowSettings;
+use std::f32::consts::PI;
+
+// 距離による減衰 1 / (constant + linear * d + quadratic * d^2)
+#[derive(Clone, Copy, Debug)]
+pub struct Attenuation {
+    pub constant: f32,
+    pub linear: f32,
+    pub quadratic: f32,
+}
+
+impl Attenuation {
+    // 減衰しない
+    pub const NONE: Attenuation = Attenuation {
+        constant: 1.0,
+        linear: 0.0,
+        quadratic: 0.0,
+    };
+
+    pub fn new(constant: f32, linear: f32, quadratic: f32) -> Self {
+        Self {
+            constant,
+            linear,
+            quadratic,
+        }
+    }
+
+    pub fn factor(&self, distance: f32) -> f32 {
+        let denominator =
+            self.constant + self.linear * distance + self.quadratic * distance * distance;
+        if denominator > 0.0 {
+            (1.0 / denominator).min(1.0)
+        } else {
+            1.0
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum LightKind {
+    // 無限遠から direction の向きに照らす平行光源
+    Directional {
+        direction: Vec3,
+    },
+    // position から全方向に照らす点光源
+    Point {
+        attenuation: Attenuation,
+    },
+    // position から direction を中心に照らすスポットライト。
+    // 角度は中心軸からの半角 (ラジアン) で、inner_angle から outer_angle にかけて暗くなる
+    Spot {
+        direction: Vec3,
+        inner_angle: f32,
+        outer_angle: f32,
+        attenuation: Attenuation,
+    },
+}
+
+// ライティング計算用の構造体
+#[derive(Clone)]
+pub struct Light {
+    pub kind: LightKind,
+    // 平行光源では影の視点を決めるのにだけ使う
+    pub position: Vec3,
+    pub color: Color,
+    pub intensity: f32,
+    // Some なら不透明なメッシュに影を落とす
+    pub shadow: Option<ShadowSettings>,
+}
+
+impl Light {
+    // 減衰しない点光源
+    pub fn new(position: Vec3, color: Color, intensity: f32) -> Self {
+        Self {
+            kind: LightKind::Point {
+                attenuation: Attenuation::NONE,
+            },
+            position,
+            color,
+            intensity,
+            shadow: None,
+        }
+    }
+
+    pub fn directional(direction: Vec3, color: Color, intensity: f32) -> Self {
+        Self {
+            kind: LightKind::Directional {
+                direction: direction.normalize(),
+            },
+            position: Vec3::new(0.0, 0.0, 0.0),
+            color,
+            intensity,
+            shadow: None,
+        }
+    }
+
+    pub fn spot(
+        position: Vec3,
+        direction: Vec3,
+        inner_angle: f32,
+        outer_angle: f32,
+        color: Color,
+        intensity: f32,
+    ) -> Self {
+        Self {
+            kind: LightKind::Spot {
+                direction: direction.normalize(),
+                inner_angle,
+                outer_angle,
+                attenuation: Attenuation::NONE,
+            },
+            position,
+            color,
+            intensity,
+            shadow: None,
+        }
+    }
+
+    // 点光源・スポットライトの減衰を設定する (平行光源では何もしない)
+    pub fn with_attenuation(mut self, attenuation: Attenuation) -> Self {
+        match &mut self.kind {
+            LightKind::Directional { .. } => {}
+            LightKind::Point { attenuation: a } | LightKind::Spot { attenuation: a, .. } => {
+                *a = attenuation;
+            }
+        }
+        self
+    }
+
+    pub fn with_shadow(mut self, shadow: ShadowSettings) -> Self {
+        self.shadow = Some(shadow);
+        self
+    }
+
+    // surface_pos から光源への向き (正規化済み) と、減衰・スポットの範囲を掛けた強さ
+    fn incident(&self, surface_pos: Vec3) -> (Vec3, f32) {
+        let to_light = self.position - surface_pos;
+        let distance = to_light.length();
+        let light_dir = to_light.normalize();
+
+        match self.kind {
+            LightKind::Directional { direction } => ((-direction).normalize(), self.intensity),
+            LightKind::Point { attenuation } => {
+                (light_dir, self.intensity * attenuation.factor(distance))
+            }
+            LightKind::Spot {
+                direction,
+                inner_angle,
+                outer_angle,
+                attenuation,
+            } => {
+                let cos_angle = -light_dir.dot(&direction.normalize());
+                let cos_inner = inner_angle.cos();
+                let cos_outer = outer_angle.cos();
+                let cone = if cos_inner > cos_outer {
+                    let t = ((cos_angle - cos_outer) / (cos_inner - cos_outer)).clamp(0.0, 1.0);
+                    t * t * (3.0 - 2.0 * t)
+                } else if cos_angle >= cos_outer {
+                    1.0
+                } else {
+                    0.0
+                };
+                (
+                    light_dir,
+                    self.intensity * attenuation.factor(distance) * cone,
+                )
+            }
+        }
+    }
+
+    // albedo の面が material に従って反射する光の RGB (x, y, z が r, g, b で 0..1 が 0..255)
+    pub fn calculate_lighting(
+        &self,
+        surface_pos: Vec3,
+        surface_normal: Vec3,
+        view_pos: Vec3,
+        material: &Material,
+        albedo: Color,
+    ) -> Vec3 {
+        let (light_dir, strength) = self.incident(surface_pos);
+        let n_dot_l = surface_normal.dot(&light_dir);
+        if strength <= 0.0 || n_dot_l <= 0.0 {
+            return Vec3::new(0.0, 0.0, 0.0);
+        }
+
+        let view_dir = (view_pos - surface_pos).normalize();
+        let half_dir = (light_dir + view_dir).normalize();
+        let n_dot_h = surface_normal.dot(&half_dir).max(0.0);
+
+        let albedo = [albedo.r, albedo.g, albedo.b].map(|c| c as f32 / 255.0);
+        let reflected = match material.model {
+            LightingModel::BlinnPhong => {
+                let diffuse = n_dot_l * (1.0 - material.specular);
+                let specular = material.specular * n_dot_h.powf(material.shininess);
+                albedo.map(|a| a * diffuse + specular)
+            }
+            LightingModel::CookTorrance => {
+                let n_dot_v = surface_normal.dot(&view_dir).max(1e-4);
+                let v_dot_h = view_dir.dot(&half_dir).max(0.0);
+                cook_torrance(material, albedo, n_dot_l, n_dot_v, n_dot_h, v_dot_h)
+            }
+        };
+
+        let radiance =
+            [self.color.r, self.color.g, self.color.b].map(|c| c as f32 / 255.0 * strength);
+        Vec3::new(
+            reflected[0] * radiance[0],
+            reflected[1] * radiance[1],
+            reflected[2] * radiance[2],
+        )
+    }
+
+    // シャドウマップを描くときのライトのビュー射影行列
+    pub fn shadow_view_projection(&self) -> Option<Mat4> {
+        let settings = self.shadow?;
+        let (eye, direction) = match self.kind {
+            // target が深度範囲の真ん中に来るように光の来る側へ視点を下げる
+            LightKind::Directional { direction } => {
+                let back = settings.projection.far() * 0.5;
+                (settings.target - direction * back, direction)
+            }
+            // 点光源は全方向ではなく target の方向だけ影を落とす
+            LightKind::Point { .. } => (self.position, settings.target - self.position),
+            LightKind::Spot { direction, .. } => (self.position, direction),
+        };
+        Some(settings.view_projection(eye, direction))
+    }
+}
+
+// Cook-Torrance (GGX + Smith-Schlick + Schlick Fresnel) の反射率に n・l を掛けたもの。
+// ライトの強さ 1 で白い拡散面が 1 になるよう π を掛けた形にしてある
+fn cook_torrance(
+    material: &Material,
+    albedo: [f32; 3],
+    n_dot_l: f32,
+    n_dot_v: f32,
+    n_dot_h: f32,
+    v_dot_h: f32,
+) -> [f32; 3] {
+    let roughness = material.roughness.clamp(0.04, 1.0);
+    let metallic = material.metallic.clamp(0.0, 1.0);
+
+    let alpha = roughness * roughness;
+    let alpha2 = alpha * alpha;
+    let denominator = n_dot_h * n_dot_h * (alpha2 - 1.0) + 1.0;
+    let distribution = alpha2 / (PI * denominator * denominator);
+
+    let k = (roughness + 1.0) * (roughness + 1.0) / 8.0;
+    let geometry = (n_dot_l / (n_dot_l * (1.0 - k) + k)) * (n_dot_v / (n_dot_v * (1.0 - k) + k));
+
+    // 誘電体は 4% を反射し、金属は自分の色で反射する
+    let fresnel_weight = (1.0 - v_dot_h).powi(5);
+    albedo.map(|a| {
+        let f0 = 0.04 * (1.0 - metallic) + a * metallic;
+        let fresnel = f0 + (1.0 - f0) * fresnel_weight;
+        let specular = distribution * geometry * fresnel / (4.0 * n_dot_l * n_dot_v);
+        let diffuse = (1.0 - fresnel) * (1.0 - metallic) * a / PI;
+        (diffuse + specular) * n_dot_l * PI
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EPSILON: f32 = 1e-5;
+
+    #[test]
+    fn attenuation_falls_off_with_distance() {
+        for distance in [0.0, 1.0, 10.0, 1000.0] {
+            assert_eq!(Attenuation::NONE.factor(distance), 1.0);
+        }
+
+        let attenuation = Attenuation::new(1.0, 0.5, 0.25);
+        assert_eq!(attenuation.factor(0.0), 1.0);
+        assert!((attenuation.factor(2.0) - 1.0 / 3.0).abs() < EPSILON);
+        assert!((attenuation.factor(4.0) - 1.0 / 7.0).abs() < EPSILON);
+        let mut previous = 1.0;
+        for i in 1..100 {
+            let factor = attenuation.factor(i as f32 * 0.5);
+            assert!(factor < previous && factor > 0.0);
+            previous = factor;
+        }
+
+        // 1 より明るくはしない
+        assert_eq!(Attenuation::new(0.1, 0.0, 0.0).factor(0.0), 1.0);
+
+        // 点光源の強さに掛かる
+        let light =
+            Light::new(Vec3::new(0.0, 2.0, 0.0), Color::WHITE, 2.0).with_attenuation(attenuation);
+        let (direction, strength) = light.incident(Vec3::new(0.0, 0.0, 0.0));
+        assert!(direction.approx_eq(&Vec3::new(0.0, 1.0, 0.0), EPSILON));
+        assert!((strength - 2.0 / 3.0).abs() < EPSILON);
+    }
+
+    #[test]
+    fn spot_cone_smoothsteps_between_inner_and_outer_angle() {
+        let (inner, outer) = (20.0_f32.to_radians(), 30.0_f32.to_radians());
+        let light = Light::spot(
+            Vec3::new(0.0, 0.0, 0.0),
+            Vec3::new(0.0, -1.0, 0.0),
+            inner,
+            outer,
+            Color::WHITE,
+            1.0,
+        );
+        // 中心軸から angle だけ離れた、ライトから距離 1 の点での強さ
+        let strength = |angle: f32| {
+            let surface = Vec3::new(angle.sin(), -angle.cos(), 0.0);
+            light.incident(surface).1
+        };
+
+        assert!((strength(0.0) - 1.0).abs() < EPSILON);
+        assert!((strength(inner - 0.01) - 1.0).abs() < EPSILON);
+        assert_eq!(strength(outer + 0.01), 0.0);
+        assert_eq!(strength(90.0_f32.to_radians()), 0.0);
+
+        let angle = 25.0_f32.to_radians();
+        let t = (angle.cos() - outer.cos()) / (inner.cos() - outer.cos());
+        assert!((strength(angle) - t * t * (3.0 - 2.0 * t)).abs() < EPSILON);
+
+        // 内側から外側へ滑らかに暗くなり、両端では傾きが 0 になる
+        let mut previous = strength(inner);
+        for i in 1..=20 {
+            let current = strength(inner + (outer - inner) * i as f32 / 20.0);
+            assert!(current <= previous);
+            previous = current;
+        }
+        let step = 1e-3;
+        assert!(strength(inner) - strength(inner + step) < 1e-3);
+        assert!(strength(outer - step) < 1e-3);
+    }
+
+    // 真上 (+Y) を向いた原点の面を、to_light の向きから照らして view_dir の向きから見た反射光
+    fn reflected(material: &Material, albedo: Color, to_light: Vec3, view_dir: Vec3) -> Vec3 {
+        let light = Light::directional(-to_light.normalize(), Color::WHITE, 1.0);
+        let origin = Vec3::new(0.0, 0.0, 0.0);
+        let normal = Vec3::new(0.0, 1.0, 0.0);
+        light.calculate_lighting(origin, normal, view_dir.normalize() * 5.0, material, albedo)
+    }
+
+    #[test]
+    fn cook_torrance_metallic_tints_specular_and_drops_diffuse() {
+        let red = Color::new(255, 0, 0);
+        let up = Vec3::new(0.0, 1.0, 0.0);
+        let dielectric = reflected(&Material::physically_based(red, 0.0, 0.3), red, up, up);
+        let metal = reflected(&Material::physically_based(red, 1.0, 0.3), red, up, up);
+
+        // 誘電体のハイライトは白く (F0 = 0.04)、金属は自分の色でしか反射しない
+        assert!(dielectric.y > 0.0 && (dielectric.y - dielectric.z).abs() < EPSILON);
+        assert!(dielectric.x > dielectric.y);
+        assert_eq!((metal.y, metal.z), (0.0, 0.0));
+        assert!(metal.x > 0.0);
+
+        // 鏡面方向から外れると、拡散反射のない金属の方が暗い
+        let side = Vec3::new(1.0, 0.3, 0.0);
+        let dielectric = reflected(&Material::physically_based(red, 0.0, 0.3), red, up, side);
+        let metal = reflected(&Material::physically_based(red, 1.0, 0.3), red, up, side);
+        assert!(metal.x < dielectric.x);
+    }
+
+    #[test]
+    fn cook_torrance_does_not_reflect_more_than_it_receives() {
+        // 白い面の反射を半球全体のライトの向きで積分すると 1 を超えない (返す値は BRDF * n・l * π)
+        let white = Color::WHITE;
+        let view = Vec3::new(1.0, 1.0, 0.0);
+        let (steps_theta, steps_phi) = (256, 256);
+        let (d_theta, d_phi) = (PI * 0.5 / steps_theta as f32, 2.0 * PI / steps_phi as f32);
+        for metallic in [0.0, 1.0] {
+            for roughness in [0.5, 0.8, 1.0] {
+                let material = Material::physically_based(white, metallic, roughness);
+                let mut total = 0.0;
+                for i in 0..steps_theta {
+                    let theta = (i as f32 + 0.5) * d_theta;
+                    for j in 0..steps_phi {
+                        let phi = (j as f32 + 0.5) * d_phi;
+                        let to_light = Vec3::new(
+                            theta.sin() * phi.cos(),
+                            theta.cos(),
+                            theta.sin() * phi.sin(),
+                        );
+                        let rgb = reflected(&material, white, to_light, view);
+                        total += rgb.x / PI * theta.sin() * d_theta * d_phi;
+                    }
+                }
+                assert!(
+                    total > 0.1 && total <= 1.0,
+                    "metallic {metallic} roughness {roughness}: {total}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn cook_torrance_stays_finite_as_roughness_goes_to_zero() {
+        let white = Color::WHITE;
+        let mirror_light = Vec3::new(-1.0, 1.0, 0.0);
+        let mirror_view = Vec3::new(1.0, 1.0, 0.0);
+        let grazing = Vec3::new(1.0, 1e-4, 0.0);
+        for roughness in [0.0, 1e-6, 1e-3, 0.04] {
+            for metallic in [0.0, 1.0] {
+                let material = Material::physically_based(white, metallic, roughness);
+                for (to_light, view) in [
+                    (mirror_light, mirror_view),
+                    (Vec3::new(0.0, 1.0, 0.0), Vec3::new(0.0, 1.0, 0.0)),
+                    (grazing, grazing),
+                    (grazing, -grazing + Vec3::new(0.0, 2e-4, 0.0)),
+                ] {
+                    let rgb = reflected(&material, white, to_light, view);
+                    for c in [rgb.x, rgb.y, rgb.z] {
+                        assert!(c.is_finite() && c >= 0.0, "roughness {roughness}: {c}");
+                    }
+                }
+            }
+        }
+    }
+}
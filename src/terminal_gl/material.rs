@@ -0,0 +1,69 @@
+use crate::geometry::Color;
+
+// ライティングの計算方法
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LightingModel {
+    // 拡散反射 + ハーフベクトルによる鏡面反射
+    BlinnPhong,
+    // GGX 分布・Smith の遮蔽・Schlick の Fresnel によるマイクロファセットモデル
+    CookTorrance,
+}
+
+#[derive(Clone, Debug)]
+pub struct Material {
+    // 三角形の色 (とテクスチャ) に掛ける色
+    pub base_color: Color,
+    // ライトに関係なく足す色
+    pub emissive: Color,
+    pub model: LightingModel,
+    // BlinnPhong の鏡面反射の割合 (残りが拡散反射) と鋭さ
+    pub specular: f32,
+    pub shininess: f32,
+    // CookTorrance の金属らしさ (0: 誘電体, 1: 金属) と表面の粗さ
+    pub metallic: f32,
+    pub roughness: f32,
+}
+
+impl Material {
+    pub fn new() -> Self {
+        Self {
+            base_color: Color::WHITE,
+            emissive: Color::BLACK,
+            model: LightingModel::BlinnPhong,
+            specular: 0.2,
+            shininess: 32.0,
+            metallic: 0.0,
+            roughness: 0.5,
+        }
+    }
+
+    pub fn blinn_phong(base_color: Color, specular: f32, shininess: f32) -> Self {
+        Self {
+            base_color,
+            specular,
+            shininess,
+            ..Self::new()
+        }
+    }
+
+    pub fn physically_based(base_color: Color, metallic: f32, roughness: f32) -> Self {
+        Self {
+            base_color,
+            model: LightingModel::CookTorrance,
+            metallic,
+            roughness,
+            ..Self::new()
+        }
+    }
+
+    pub fn with_emissive(mut self, emissive: Color) -> Self {
+        self.emissive = emissive;
+        self
+    }
+}
+
+impl Default for Material {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -1,4 +1,5 @@
-use crate::geometry::{Vec2, Vec3};
+use crate::geometry::{Quat, Vec3, Vec4};
+use std::ops::Mul;
 
 #[derive(Clone, Copy, Debug)]
 pub struct Mat4 {
@@ -29,6 +30,9 @@ impl Mat4 {
         mat
     }
 
+    // 軸ごとの回転行列。Transform はクォータニオンから行列を作るので、
+    // テストで Quat と比べる参照としてだけ使う
+    #[cfg(test)]
     pub fn rotation_x(angle: f32) -> Self {
         let mut mat = Self::identity();
         let cos_a = angle.cos();
@@ -40,6 +44,7 @@ impl Mat4 {
         mat
     }
 
+    #[cfg(test)]
     pub fn rotation_y(angle: f32) -> Self {
         let mut mat = Self::identity();
         let cos_a = angle.cos();
@@ -51,6 +56,7 @@ impl Mat4 {
         mat
     }
 
+    #[cfg(test)]
     pub fn rotation_z(angle: f32) -> Self {
         let mut mat = Self::identity();
         let cos_a = angle.cos();
@@ -81,26 +87,34 @@ impl Mat4 {
         mat
     }
 
+    // 正射影 (ビュー空間の -near..-far を NDC の -1..1 に写す)
+    pub fn orthographic(left: f32, right: f32, bottom: f32, top: f32, near: f32, far: f32) -> Self {
+        let mut mat = Self::identity();
+        mat.m[0][0] = 2.0 / (right - left);
+        mat.m[1][1] = 2.0 / (top - bottom);
+        mat.m[2][2] = -2.0 / (far - near);
+        mat.m[0][3] = -(right + left) / (right - left);
+        mat.m[1][3] = -(top + bottom) / (top - bottom);
+        mat.m[2][3] = -(far + near) / (far - near);
+        mat
+    }
+
     pub fn look_at(eye: Vec3, center: Vec3, up: Vec3) -> Self {
-        let f = Vec3 {
-            x: center.x - eye.x,
-            y: center.y - eye.y,
-            z: center.z - eye.z,
-        }
-        .normalize();
+        let f = (center - eye).normalize();
 
         let s = f.cross(&up).normalize();
         let u = s.cross(&f);
 
         let mut mat = Self::identity();
+        // 各行がカメラの右・上・後ろ方向 (行優先なので回転部分は基底を行に並べる)
         mat.m[0][0] = s.x;
-        mat.m[1][0] = s.y;
-        mat.m[2][0] = s.z;
-        mat.m[0][1] = u.x;
+        mat.m[0][1] = s.y;
+        mat.m[0][2] = s.z;
+        mat.m[1][0] = u.x;
         mat.m[1][1] = u.y;
-        mat.m[2][1] = u.z;
-        mat.m[0][2] = -f.x;
-        mat.m[1][2] = -f.y;
+        mat.m[1][2] = u.z;
+        mat.m[2][0] = -f.x;
+        mat.m[2][1] = -f.y;
         mat.m[2][2] = -f.z;
         mat.m[0][3] = -s.dot(&eye);
         mat.m[1][3] = -u.dot(&eye);
@@ -120,6 +134,107 @@ impl Mat4 {
         result
     }
 
+    // すべての要素の差が epsilon 以下
+    #[cfg(test)]
+    pub fn approx_eq(&self, other: &Mat4, epsilon: f32) -> bool {
+        (0..4).all(|i| (0..4).all(|j| (self.m[i][j] - other.m[i][j]).abs() <= epsilon))
+    }
+
+    pub fn transpose(&self) -> Mat4 {
+        let mut result = Mat4::zero();
+        for i in 0..4 {
+            for j in 0..4 {
+                result.m[i][j] = self.m[j][i];
+            }
+        }
+        result
+    }
+
+    pub fn determinant(&self) -> f32 {
+        let (s, c) = self.minors();
+        s[0] * c[5] - s[1] * c[4] + s[2] * c[3] + s[3] * c[2] - s[4] * c[1] + s[5] * c[0]
+    }
+
+    // 逆行列。行列式が 0 に近く逆行列がないときは None
+    pub fn inverse(&self) -> Option<Mat4> {
+        let m = &self.m;
+        let (s, c) = self.minors();
+        let det = self.determinant();
+        if det.abs() < f32::EPSILON * f32::EPSILON {
+            return None;
+        }
+        let inv = 1.0 / det;
+
+        // 余因子行列の転置を行列式で割る
+        let mut result = Mat4::zero();
+        let r = &mut result.m;
+        r[0][0] = (m[1][1] * c[5] - m[1][2] * c[4] + m[1][3] * c[3]) * inv;
+        r[0][1] = (-m[0][1] * c[5] + m[0][2] * c[4] - m[0][3] * c[3]) * inv;
+        r[0][2] = (m[3][1] * s[5] - m[3][2] * s[4] + m[3][3] * s[3]) * inv;
+        r[0][3] = (-m[2][1] * s[5] + m[2][2] * s[4] - m[2][3] * s[3]) * inv;
+        r[1][0] = (-m[1][0] * c[5] + m[1][2] * c[2] - m[1][3] * c[1]) * inv;
+        r[1][1] = (m[0][0] * c[5] - m[0][2] * c[2] + m[0][3] * c[1]) * inv;
+        r[1][2] = (-m[3][0] * s[5] + m[3][2] * s[2] - m[3][3] * s[1]) * inv;
+        r[1][3] = (m[2][0] * s[5] - m[2][2] * s[2] + m[2][3] * s[1]) * inv;
+        r[2][0] = (m[1][0] * c[4] - m[1][1] * c[2] + m[1][3] * c[0]) * inv;
+        r[2][1] = (-m[0][0] * c[4] + m[0][1] * c[2] - m[0][3] * c[0]) * inv;
+        r[2][2] = (m[3][0] * s[4] - m[3][1] * s[2] + m[3][3] * s[0]) * inv;
+        r[2][3] = (-m[2][0] * s[4] + m[2][1] * s[2] - m[2][3] * s[0]) * inv;
+        r[3][0] = (-m[1][0] * c[3] + m[1][1] * c[1] - m[1][2] * c[0]) * inv;
+        r[3][1] = (m[0][0] * c[3] - m[0][1] * c[1] + m[0][2] * c[0]) * inv;
+        r[3][2] = (-m[3][0] * s[3] + m[3][1] * s[1] - m[3][2] * s[0]) * inv;
+        r[3][3] = (m[2][0] * s[3] - m[2][1] * s[1] + m[2][2] * s[0]) * inv;
+        Some(result)
+    }
+
+    // 上 2 行 (s) と下 2 行 (c) から取った 2x2 小行列式。
+    // 行列式と余因子はこの 12 個の組み合わせで書ける
+    fn minors(&self) -> ([f32; 6], [f32; 6]) {
+        let m = &self.m;
+        let s = [
+            m[0][0] * m[1][1] - m[1][0] * m[0][1],
+            m[0][0] * m[1][2] - m[1][0] * m[0][2],
+            m[0][0] * m[1][3] - m[1][0] * m[0][3],
+            m[0][1] * m[1][2] - m[1][1] * m[0][2],
+            m[0][1] * m[1][3] - m[1][1] * m[0][3],
+            m[0][2] * m[1][3] - m[1][2] * m[0][3],
+        ];
+        let c = [
+            m[2][0] * m[3][1] - m[3][0] * m[2][1],
+            m[2][0] * m[3][2] - m[3][0] * m[2][2],
+            m[2][0] * m[3][3] - m[3][0] * m[2][3],
+            m[2][1] * m[3][2] - m[3][1] * m[2][2],
+            m[2][1] * m[3][3] - m[3][1] * m[2][3],
+            m[2][2] * m[3][3] - m[3][2] * m[2][3],
+        ];
+        (s, c)
+    }
+
+    // 法線を変換する行列 (左上 3x3 の逆転置)。
+    // 不均一な拡大でも面に垂直なまま保たれる。逆行列がなければ元の行列を使う
+    pub fn normal_matrix(&self) -> Mat4 {
+        let mut linear = *self;
+        for i in 0..3 {
+            linear.m[i][3] = 0.0;
+            linear.m[3][i] = 0.0;
+        }
+        linear.m[3][3] = 1.0;
+        match linear.inverse() {
+            Some(inverse) => inverse.transpose(),
+            None => linear,
+        }
+    }
+
+    // 向きを変換する (w = 0 として平行移動を無視する)
+    pub fn transform_vector(&self, vector: Vec3) -> Vec3 {
+        let m = &self.m;
+        Vec3::new(
+            m[0][0] * vector.x + m[0][1] * vector.y + m[0][2] * vector.z,
+            m[1][0] * vector.x + m[1][1] * vector.y + m[1][2] * vector.z,
+            m[2][0] * vector.x + m[2][1] * vector.y + m[2][2] * vector.z,
+        )
+    }
+
     pub fn transform_point(&self, point: Vec3) -> Vec3 {
         let x =
             self.m[0][0] * point.x + self.m[0][1] * point.y + self.m[0][2] * point.z + self.m[0][3];
@@ -137,19 +252,40 @@ impl Mat4 {
         }
     }
 
-    pub fn project_to_screen(&self, point: Vec3, width: f32, height: f32) -> Vec2 {
-        let transformed = self.transform_point(point);
-        Vec2::new(
-            (transformed.x + 1.0) * width * 0.5,
-            (1.0 - transformed.y) * height * 0.5,
-        )
+    // w で割る前のクリップ座標 [x, y, z, w]
+    pub fn transform_homogeneous(&self, point: Vec3) -> [f32; 4] {
+        let mut out = [0.0; 4];
+        for (i, v) in out.iter_mut().enumerate() {
+            *v = self.m[i][0] * point.x + self.m[i][1] * point.y + self.m[i][2] * point.z + self.m[i][3];
+        }
+        out
+    }
+}
+
+impl Mul for Mat4 {
+    type Output = Mat4;
+
+    fn mul(self, other: Mat4) -> Mat4 {
+        self.multiply(&other)
+    }
+}
+
+impl Mul<Vec4> for Mat4 {
+    type Output = Vec4;
+
+    fn mul(self, v: Vec4) -> Vec4 {
+        let row = |i: usize| {
+            let m = &self.m[i];
+            m[0] * v.x + m[1] * v.y + m[2] * v.z + m[3] * v.w
+        };
+        Vec4::new(row(0), row(1), row(2), row(3))
     }
 }
 
 #[derive(Clone)]
 pub struct Transform {
     pub position: Vec3,
-    pub rotation: Vec3,
+    pub rotation: Quat,
     pub scale: Vec3,
 }
 
@@ -157,20 +293,33 @@ impl Transform {
     pub fn new() -> Self {
         Self {
             position: Vec3::new(0.0, 0.0, 0.0),
-            rotation: Vec3::new(0.0, 0.0, 0.0),
+            rotation: Quat::IDENTITY,
             scale: Vec3::new(1.0, 1.0, 1.0),
         }
     }
 
+    // X, Y, Z 軸まわりの角度 (ラジアン) で回転を設定する。Z·Y·X の順に回す
+    pub fn set_euler(&mut self, euler: Vec3) {
+        self.rotation = Quat::from_euler(euler);
+    }
+
+    pub fn euler(&self) -> Vec3 {
+        self.rotation.to_euler()
+    }
+
+    // y 軸の角度だけを置き換え、ほかの 2 軸の角度は保つ
+    pub fn set_rotation_y(&mut self, angle: f32) {
+        let mut euler = self.euler();
+        euler.y = angle;
+        self.set_euler(euler);
+    }
+
     pub fn to_matrix(&self) -> Mat4 {
         let translation = Mat4::translation(self.position.x, self.position.y, self.position.z);
-        let rotation_x = Mat4::rotation_x(self.rotation.x);
-        let rotation_y = Mat4::rotation_y(self.rotation.y);
-        let rotation_z = Mat4::rotation_z(self.rotation.z);
+        let rotation = self.rotation.to_matrix();
         let scale = Mat4::scale(self.scale.x, self.scale.y, self.scale.z);
 
-        translation
-            .multiply(&rotation_z.multiply(&rotation_y.multiply(&rotation_x.multiply(&scale))))
+        translation.multiply(&rotation.multiply(&scale))
     }
 }
 
@@ -179,3 +328,193 @@ impl Default for Transform {
         Self::new()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EPSILON: f32 = 1e-4;
+
+    // 再現できるように固定の種を使う線形合同法で [min, max) の値を作る
+    struct Lcg(u32);
+
+    impl Lcg {
+        fn next(&mut self, min: f32, max: f32) -> f32 {
+            self.0 = self.0.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+            min + (self.0 >> 8) as f32 / (1 << 24) as f32 * (max - min)
+        }
+
+        fn vec3(&mut self, min: f32, max: f32) -> Vec3 {
+            Vec3::new(self.next(min, max), self.next(min, max), self.next(min, max))
+        }
+
+        // 不均一な拡大を含む平行移動・回転・拡大の合成
+        fn trs(&mut self) -> Mat4 {
+            let mut transform = Transform::new();
+            transform.position = self.vec3(-5.0, 5.0);
+            transform.set_euler(self.vec3(-3.0, 3.0));
+            transform.scale = self.vec3(0.2, 3.0);
+            transform.to_matrix()
+        }
+    }
+
+    fn sample_matrices() -> Vec<Mat4> {
+        let mut rng = Lcg(12345);
+        let mut matrices: Vec<Mat4> = (0..50).map(|_| rng.trs()).collect();
+        matrices.push(Mat4::perspective(1.0, 1.5, 0.1, 100.0));
+        matrices.push(Mat4::orthographic(-3.0, 2.0, -1.0, 4.0, 0.5, 20.0));
+        matrices.push(Mat4::look_at(
+            Vec3::new(1.0, 2.0, 3.0),
+            Vec3::new(-1.0, 0.0, 0.5),
+            Vec3::new(0.0, 1.0, 0.0),
+        ));
+        matrices.push(Mat4::perspective(0.8, 1.0, 0.1, 50.0).multiply(&rng.trs()));
+        matrices
+    }
+
+    fn relative_eq(a: f32, b: f32) -> bool {
+        (a - b).abs() <= EPSILON * a.abs().max(b.abs()).max(1.0)
+    }
+
+    #[test]
+    fn inverse_gives_identity() {
+        for m in sample_matrices() {
+            let inverse = m.inverse().expect("invertible");
+            assert!(m.multiply(&inverse).approx_eq(&Mat4::identity(), EPSILON), "{m:?}");
+            assert!(inverse.multiply(&m).approx_eq(&Mat4::identity(), EPSILON), "{m:?}");
+        }
+    }
+
+    #[test]
+    fn inverse_of_zero_scale_is_none() {
+        let mut transform = Transform::new();
+        transform.position = Vec3::new(1.0, 2.0, 3.0);
+        transform.set_euler(Vec3::new(0.3, 0.2, 0.1));
+        for scale in [
+            Vec3::new(0.0, 1.0, 1.0),
+            Vec3::new(2.0, 0.0, 1.0),
+            Vec3::new(0.0, 0.0, 0.0),
+        ] {
+            transform.scale = scale;
+            let m = transform.to_matrix();
+            assert_eq!(m.determinant(), 0.0);
+            assert!(m.inverse().is_none());
+        }
+        assert!(Mat4::zero().inverse().is_none());
+    }
+
+    #[test]
+    fn transpose_twice_is_identity_operation() {
+        for m in sample_matrices() {
+            let t = m.transpose();
+            assert_eq!(t.transpose().m, m.m);
+            assert_eq!(t.m[0][3], m.m[3][0]);
+            assert!(relative_eq(t.determinant(), m.determinant()));
+        }
+    }
+
+    #[test]
+    fn determinant_is_multiplicative() {
+        let matrices = sample_matrices();
+        for pair in matrices.windows(2) {
+            let (a, b) = (pair[0], pair[1]);
+            let product = a.multiply(&b).determinant();
+            assert!(relative_eq(product, a.determinant() * b.determinant()), "{a:?} {b:?}");
+        }
+    }
+
+    #[test]
+    fn determinant_of_trs_is_scale_volume() {
+        let mut transform = Transform::new();
+        transform.position = Vec3::new(4.0, -1.0, 2.0);
+        transform.set_euler(Vec3::new(1.0, -0.5, 2.0));
+        transform.scale = Vec3::new(2.0, 0.5, 3.0);
+        assert!(relative_eq(transform.to_matrix().determinant(), 3.0));
+    }
+
+    #[test]
+    fn normal_matrix_keeps_normals_perpendicular() {
+        let mut rng = Lcg(777);
+        for m in sample_matrices() {
+            // 面の接線 2 本と法線を変換し、法線が変換後の接線にも垂直か調べる
+            let normal = rng.vec3(-1.0, 1.0).normalize();
+            let helper = if normal.x.abs() < 0.9 {
+                Vec3::new(1.0, 0.0, 0.0)
+            } else {
+                Vec3::new(0.0, 1.0, 0.0)
+            };
+            let tangent = normal.cross(&helper).normalize();
+            let bitangent = normal.cross(&tangent);
+
+            let n = m.normal_matrix().transform_vector(normal).normalize();
+            for t in [tangent, bitangent] {
+                let t = m.transform_vector(t).normalize();
+                assert!(n.dot(&t).abs() < EPSILON, "{m:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn normal_matrix_differs_from_model_under_non_uniform_scale() {
+        let m = Mat4::scale(4.0, 1.0, 1.0);
+        let normal = Vec3::new(1.0, 1.0, 0.0).normalize();
+        let tangent = Vec3::new(1.0, -1.0, 0.0);
+        // モデル行列そのままで変換すると法線が面に垂直でなくなる
+        let naive = m.transform_vector(normal);
+        assert!(naive.dot(&m.transform_vector(tangent)).abs() > 1.0);
+        let n = m.normal_matrix().transform_vector(normal);
+        assert!(n.dot(&m.transform_vector(tangent)).abs() < EPSILON);
+    }
+
+    #[test]
+    fn transform_vector_ignores_translation() {
+        let m = Mat4::translation(5.0, -2.0, 1.0).multiply(&Mat4::scale(2.0, 3.0, 4.0));
+        let v = Vec3::new(1.0, 1.0, 1.0);
+        assert!(m.transform_vector(v).approx_eq(&Vec3::new(2.0, 3.0, 4.0), EPSILON));
+        assert!(m.transform_point(v).approx_eq(&Vec3::new(7.0, 1.0, 5.0), EPSILON));
+    }
+
+    #[test]
+    fn look_at_maps_eye_to_origin_and_target_to_negative_z() {
+        let up = Vec3::new(0.0, 1.0, 0.0);
+        let cases = [
+            (Vec3::new(0.0, 0.0, 5.0), Vec3::new(0.0, 0.0, 0.0)),
+            (Vec3::new(3.0, 2.0, -4.0), Vec3::new(-1.0, 0.5, 2.0)),
+            (Vec3::new(-6.0, 8.0, 1.0), Vec3::new(0.0, 0.0, 0.0)),
+            (Vec3::new(2.0, -3.0, 0.5), Vec3::new(2.5, -1.0, -7.0)),
+        ];
+        for (eye, target) in cases {
+            let view = Mat4::look_at(eye, target, up);
+            let distance = (target - eye).length();
+            assert!(view.transform_point(eye).approx_eq(&Vec3::new(0.0, 0.0, 0.0), EPSILON));
+            let target_view = view.transform_point(target);
+            assert!(
+                target_view.approx_eq(&Vec3::new(0.0, 0.0, -distance), EPSILON),
+                "{eye:?} -> {target:?}: {target_view:?}"
+            );
+            // 回転部分は直交行列で、ワールドの上方向は画面の上側に来る
+            assert!(relative_eq(view.determinant(), 1.0));
+            assert!(view.transform_vector(up).y > 0.0);
+        }
+    }
+
+    #[test]
+    fn orthographic_maps_box_to_ndc_cube() {
+        let m = Mat4::orthographic(-3.0, 2.0, -1.0, 4.0, 0.5, 20.0);
+        let near = m.transform_point(Vec3::new(-3.0, -1.0, -0.5));
+        let far = m.transform_point(Vec3::new(2.0, 4.0, -20.0));
+        assert!(near.approx_eq(&Vec3::new(-1.0, -1.0, -1.0), EPSILON));
+        assert!(far.approx_eq(&Vec3::new(1.0, 1.0, 1.0), EPSILON));
+    }
+
+    #[test]
+    fn set_rotation_y_keeps_other_angles() {
+        let mut transform = Transform::new();
+        transform.set_euler(Vec3::new(0.3, -0.6, 1.2));
+
+        transform.set_rotation_y(0.4);
+        assert!(transform.euler().approx_eq(&Vec3::new(0.3, 0.4, 1.2), EPSILON));
+        transform.set_rotation_y(-1.0);
+        assert!(transform.euler().approx_eq(&Vec3::new(0.3, -1.0, 1.2), EPSILON));
+    }
+}
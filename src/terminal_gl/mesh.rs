@@ -1,7 +1,19 @@
 use crate::geometry::{Vec2, Vec3, Color};
 use crate::matrix::{Mat4, Transform};
-use crate::terminal_gl::{Canvas, ColoredCoord};
-use crate::geometry::{draw_line, draw_triangle_wireframe, draw_triangle_filled};
+use crate::terminal_gl::{BlendMode, Canvas, ColoredCoord};
+use crate::edges::{find_edges, is_feature_edge};
+use crate::geometry::{blend_pixel, draw_line, draw_triangle_wireframe, draw_triangle_wireframe_aa};
+use crate::raster::{
+    CENTER_SAMPLE, LineFragment, ScreenVertex, clip_line_near, clip_segment_to_viewport,
+    is_back_facing, rasterize_clipped, rasterize_clipped_sampled, rasterize_line,
+    rasterize_line_aa, sample_pattern,
+};
+use crate::renderer::{Fog, ToonSettings};
+use crate::shader::{FragmentInput, Shader, Uniforms, Varyings};
+use crate::shadow::ShadowMap;
+use crate::material::Material;
+use crate::texture::{Sampler, Texture};
+use std::rc::Rc;
 
 #[derive(Clone)]
 pub struct Vertex {
@@ -18,12 +30,79 @@ impl Vertex {
             uv: Vec2::new(0.0, 0.0),
         }
     }
+
+    pub fn with_attributes(position: Vec3, normal: Vec3, uv: Vec2) -> Self {
+        Self {
+            position,
+            normal,
+            uv,
+        }
+    }
 }
 
 #[derive(Clone)]
 pub struct Triangle {
     pub vertices: [usize; 3],
     pub color: Color,
+    // None ならメッシュのマテリアルを使う
+    pub material: Option<Rc<Material>>,
+}
+
+// 線で描く辺の選び方
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum EdgeSelection {
+    // 三角形のすべての辺
+    All,
+    // 境界・シルエット・折り目 (表の面同士で crease_angle ラジアンを超える) の辺だけ
+    Feature { crease_angle: f32 },
+}
+
+// 辺の描き方
+#[derive(Clone, Copy, Debug)]
+pub struct EdgeStyle {
+    // None なら面の色を使う
+    pub color: Option<Color>,
+    // Some なら深度バッファより (bias 分まで) 奥にあるピクセルを描かない
+    pub depth_bias: Option<f32>,
+    pub anti_aliased: bool,
+    pub depth_cue: Option<DepthCue>,
+    pub fog: Option<Fog>,
+}
+
+// 深度キュー。視点から near より遠い線を far に向かって min_intensity まで暗くする
+#[derive(Clone, Copy, Debug)]
+pub struct DepthCue {
+    pub near: f32,
+    pub far: f32,
+    pub min_intensity: f32,
+}
+
+impl DepthCue {
+    pub fn apply(&self, color: Color, distance: f32) -> Color {
+        let range = (self.far - self.near).max(f32::EPSILON);
+        let t = ((distance - self.near) / range).clamp(0.0, 1.0);
+        color.multiply(1.0 - t * (1.0 - self.min_intensity))
+    }
+}
+
+impl Default for DepthCue {
+    fn default() -> Self {
+        Self {
+            near: 3.0,
+            far: 10.0,
+            min_intensity: 0.2,
+        }
+    }
+}
+
+// 半透明の面の重ね方
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Transparency {
+    // 三角形を奥から順に並べて合成する。交差する面では順序が崩れる
+    Sorted,
+    // weighted blended OIT。並べ替えずに蓄積して最後に resolve する近似。
+    // BlendMode::Alpha だけが対象で、Additive と Multiply はもともと順序に依存しない
+    WeightedBlended,
 }
 
 #[derive(Clone)]
@@ -31,6 +110,13 @@ pub struct Mesh {
     pub vertices: Vec<Vertex>,
     pub triangles: Vec<Triangle>,
     pub transform: Transform,
+    pub texture: Option<Rc<Texture>>,
+    pub sampler: Sampler,
+    // 三角形にマテリアルがなければこれを使う
+    pub material: Rc<Material>,
+    // Opaque 以外は不透明なメッシュの後に奥から順に描く
+    pub blend_mode: BlendMode,
+    pub opacity: f32,
 }
 
 impl Mesh {
@@ -39,172 +125,550 @@ impl Mesh {
             vertices: Vec::new(),
             triangles: Vec::new(),
             transform: Transform::new(),
+            texture: None,
+            sampler: Sampler::default(),
+            material: Rc::new(Material::new()),
+            blend_mode: BlendMode::Opaque,
+            opacity: 1.0,
         }
     }
 
-    // キューブの作成
+    pub fn set_texture(&mut self, texture: Texture) {
+        self.texture = Some(Rc::new(texture));
+    }
+
+    pub fn set_material(&mut self, material: Material) {
+        self.material = Rc::new(material);
+    }
+
+    pub fn material_of<'a>(&'a self, triangle: &'a Triangle) -> &'a Material {
+        triangle.material.as_deref().unwrap_or(&self.material)
+    }
+
+    // キューブの作成 (面ごとに法線と UV を持つ 24 頂点)
     pub fn create_cube(size: f32) -> Self {
         let s = size * 0.5;
-        let vertices = vec![
+        // (法線, 外側から見て反時計回りの 4 隅, 色)
+        let faces = [
             // Front face
-            Vertex::new(Vec3::new(-s, -s, s)),
-            Vertex::new(Vec3::new(s, -s, s)),
-            Vertex::new(Vec3::new(s, s, s)),
-            Vertex::new(Vec3::new(-s, s, s)),
+            (
+                Vec3::new(0.0, 0.0, 1.0),
+                [(-s, -s, s), (s, -s, s), (s, s, s), (-s, s, s)],
+                Color::RED,
+            ),
             // Back face
-            Vertex::new(Vec3::new(-s, -s, -s)),
-            Vertex::new(Vec3::new(s, -s, -s)),
-            Vertex::new(Vec3::new(s, s, -s)),
-            Vertex::new(Vec3::new(-s, s, -s)),
-        ];
-
-        let triangles = vec![
-            // Front face
-            Triangle { vertices: [0, 1, 2], color: Color::RED },
-            Triangle { vertices: [0, 2, 3], color: Color::RED },
-            // Back face
-            Triangle { vertices: [4, 6, 5], color: Color::GREEN },
-            Triangle { vertices: [4, 7, 6], color: Color::GREEN },
+            (
+                Vec3::new(0.0, 0.0, -1.0),
+                [(s, -s, -s), (-s, -s, -s), (-s, s, -s), (s, s, -s)],
+                Color::GREEN,
+            ),
             // Left face
-            Triangle { vertices: [4, 0, 3], color: Color::BLUE },
-            Triangle { vertices: [4, 3, 7], color: Color::BLUE },
+            (
+                Vec3::new(-1.0, 0.0, 0.0),
+                [(-s, -s, -s), (-s, -s, s), (-s, s, s), (-s, s, -s)],
+                Color::BLUE,
+            ),
             // Right face
-            Triangle { vertices: [1, 5, 6], color: Color::YELLOW },
-            Triangle { vertices: [1, 6, 2], color: Color::YELLOW },
+            (
+                Vec3::new(1.0, 0.0, 0.0),
+                [(s, -s, s), (s, -s, -s), (s, s, -s), (s, s, s)],
+                Color::YELLOW,
+            ),
             // Top face
-            Triangle { vertices: [3, 2, 6], color: Color::CYAN },
-            Triangle { vertices: [3, 6, 7], color: Color::CYAN },
+            (
+                Vec3::new(0.0, 1.0, 0.0),
+                [(-s, s, s), (s, s, s), (s, s, -s), (-s, s, -s)],
+                Color::CYAN,
+            ),
             // Bottom face
-            Triangle { vertices: [4, 1, 0], color: Color::MAGENTA },
-            Triangle { vertices: [4, 5, 1], color: Color::MAGENTA },
+            (
+                Vec3::new(0.0, -1.0, 0.0),
+                [(-s, -s, -s), (s, -s, -s), (s, -s, s), (-s, -s, s)],
+                Color::MAGENTA,
+            ),
+        ];
+        let uvs = [
+            Vec2::new(0.0, 0.0),
+            Vec2::new(1.0, 0.0),
+            Vec2::new(1.0, 1.0),
+            Vec2::new(0.0, 1.0),
         ];
 
+        let mut vertices = Vec::with_capacity(24);
+        let mut triangles = Vec::with_capacity(12);
+        for (normal, corners, color) in faces {
+            let base = vertices.len();
+            for (corner, uv) in corners.iter().zip(uvs) {
+                let position = Vec3::new(corner.0, corner.1, corner.2);
+                vertices.push(Vertex::with_attributes(position, normal, uv));
+            }
+            for vertices in [[base, base + 1, base + 2], [base, base + 2, base + 3]] {
+                triangles.push(Triangle { vertices, color, material: None });
+            }
+        }
+
         Self {
             vertices,
             triangles,
-            transform: Transform::new(),
+            ..Self::new()
         }
     }
 
     // 平面の作成
     pub fn create_plane(size: f32) -> Self {
         let s = size * 0.5;
+        let up = Vec3::new(0.0, 1.0, 0.0);
         let vertices = vec![
-            Vertex::new(Vec3::new(-s, 0.0, -s)),
-            Vertex::new(Vec3::new(s, 0.0, -s)),
-            Vertex::new(Vec3::new(s, 0.0, s)),
-            Vertex::new(Vec3::new(-s, 0.0, s)),
+            Vertex::with_attributes(Vec3::new(-s, 0.0, -s), up, Vec2::new(0.0, 1.0)),
+            Vertex::with_attributes(Vec3::new(s, 0.0, -s), up, Vec2::new(1.0, 1.0)),
+            Vertex::with_attributes(Vec3::new(s, 0.0, s), up, Vec2::new(1.0, 0.0)),
+            Vertex::with_attributes(Vec3::new(-s, 0.0, s), up, Vec2::new(0.0, 0.0)),
         ];
 
+        // 上 (+Y) から見て反時計回り
         let triangles = vec![
-            Triangle { vertices: [0, 1, 2], color: Color::WHITE },
-            Triangle { vertices: [0, 2, 3], color: Color::WHITE },
+            Triangle { vertices: [0, 2, 1], color: Color::WHITE, material: None },
+            Triangle { vertices: [0, 3, 2], color: Color::WHITE, material: None },
         ];
 
         Self {
             vertices,
             triangles,
-            transform: Transform::new(),
+            ..Self::new()
         }
     }
 
-    // ピラミッドの作成
+    // ピラミッドの作成 (面ごとに法線を持つ 16 頂点)
     pub fn create_pyramid(size: f32) -> Self {
         let s = size * 0.5;
-        let vertices = vec![
-            // Base
-            Vertex::new(Vec3::new(-s, -s, -s)),
-            Vertex::new(Vec3::new(s, -s, -s)),
-            Vertex::new(Vec3::new(s, -s, s)),
-            Vertex::new(Vec3::new(-s, -s, s)),
-            // Apex
-            Vertex::new(Vec3::new(0.0, s, 0.0)),
+        let base = [
+            Vec3::new(-s, -s, -s),
+            Vec3::new(s, -s, -s),
+            Vec3::new(s, -s, s),
+            Vec3::new(-s, -s, s),
         ];
+        let apex = Vec3::new(0.0, s, 0.0);
 
-        let triangles = vec![
-            // Base
-            Triangle { vertices: [0, 2, 1], color: Color::RED },
-            Triangle { vertices: [0, 3, 2], color: Color::RED },
-            // Sides
-            Triangle { vertices: [0, 1, 4], color: Color::GREEN },
-            Triangle { vertices: [1, 2, 4], color: Color::BLUE },
-            Triangle { vertices: [2, 3, 4], color: Color::YELLOW },
-            Triangle { vertices: [3, 0, 4], color: Color::CYAN },
+        let mut vertices = Vec::with_capacity(16);
+        let mut triangles = Vec::with_capacity(6);
+
+        // Base
+        let down = Vec3::new(0.0, -1.0, 0.0);
+        let base_uvs = [
+            Vec2::new(0.0, 0.0),
+            Vec2::new(1.0, 0.0),
+            Vec2::new(1.0, 1.0),
+            Vec2::new(0.0, 1.0),
         ];
+        for (position, uv) in base.iter().zip(base_uvs) {
+            vertices.push(Vertex::with_attributes(*position, down, uv));
+        }
+        triangles.push(Triangle { vertices: [0, 1, 2], color: Color::RED, material: None });
+        triangles.push(Triangle { vertices: [0, 2, 3], color: Color::RED, material: None });
+
+        // Sides
+        let side_colors = [Color::GREEN, Color::BLUE, Color::YELLOW, Color::CYAN];
+        for (i, color) in side_colors.into_iter().enumerate() {
+            let a = base[i];
+            let b = base[(i + 1) % 4];
+            let normal = (apex - a).cross(&(b - a)).normalize();
+
+            let first = vertices.len();
+            vertices.push(Vertex::with_attributes(a, normal, Vec2::new(0.0, 0.0)));
+            vertices.push(Vertex::with_attributes(apex, normal, Vec2::new(0.5, 1.0)));
+            vertices.push(Vertex::with_attributes(b, normal, Vec2::new(1.0, 0.0)));
+            triangles.push(Triangle {
+                vertices: [first, first + 1, first + 2],
+                color,
+                material: None,
+            });
+        }
 
         Self {
             vertices,
             triangles,
-            transform: Transform::new(),
+            ..Self::new()
         }
     }
 
+    // clip は vertex_batch::transform_to_clip で変換した頂点ごとのクリップ座標
     pub fn render_wireframe(
         &self,
-        canvas: &Canvas,
-        view_projection: &Mat4,
+        canvas: &mut Canvas,
+        clip: &[[f32; 4]],
+        anti_aliased: bool,
         pixels: &mut Vec<ColoredCoord>
     ) {
-        let model_matrix = self.transform.to_matrix();
-        let mvp = view_projection.multiply(&model_matrix);
+        let (width, height) = (canvas.width as f32, canvas.height as f32);
+        let screen = |i: usize| ScreenVertex::from_clip(clip[i], width, height).position;
 
         for triangle in &self.triangles {
-            let v0 = &self.vertices[triangle.vertices[0]];
-            let v1 = &self.vertices[triangle.vertices[1]];
-            let v2 = &self.vertices[triangle.vertices[2]];
+            let [p0, p1, p2] = triangle.vertices.map(screen);
+
+            if anti_aliased {
+                draw_triangle_wireframe_aa(p0, p1, p2, canvas, triangle.color);
+            } else {
+                draw_triangle_wireframe(p0, p1, p2, canvas, triangle.color, pixels);
+            }
+        }
+    }
 
-            let p0 = mvp.project_to_screen(v0.position, canvas.width as f32, canvas.height as f32);
-            let p1 = mvp.project_to_screen(v1.position, canvas.width as f32, canvas.height as f32);
-            let p2 = mvp.project_to_screen(v2.position, canvas.width as f32, canvas.height as f32);
+    pub fn is_transparent(&self) -> bool {
+        self.blend_mode != BlendMode::Opaque
+    }
 
-            draw_triangle_wireframe(p0, p1, p2, canvas, triangle.color, pixels);
+    // 深度だけを書き込む (隠線処理の前準備)
+    pub fn render_depth(&self, canvas: &mut Canvas, clip: &[[f32; 4]]) {
+        let (width, height) = (canvas.width, canvas.height);
+        for triangle in &self.triangles {
+            let triangle_clip = triangle.vertices.map(|i| clip[i]);
+            if is_back_facing(&triangle_clip) {
+                continue;
+            }
+            rasterize_clipped(triangle_clip, width, height, |quad| {
+                for (fragment, covered) in quad.fragments.iter().zip(quad.coverage) {
+                    if covered {
+                        canvas.depth_test(fragment.x, fragment.y, fragment.depth);
+                    }
+                }
+            });
         }
     }
 
-    pub fn render_filled(
+    // 辺を線で描く。アンチエイリアス線はキャンバスに直接合成し、それ以外は pixels に積む
+    pub fn render_edges(
+        &self,
+        canvas: &mut Canvas,
+        clip: &[[f32; 4]],
+        selection: EdgeSelection,
+        style: &EdgeStyle,
+        pixels: &mut Vec<ColoredCoord>,
+    ) {
+        let front_facing: Vec<bool> = self
+            .triangles
+            .iter()
+            .map(|t| !is_back_facing(&t.vertices.map(|i| clip[i])))
+            .collect();
+
+        let (width, height) = (canvas.width, canvas.height);
+        for edge in find_edges(self) {
+            let visible = match selection {
+                EdgeSelection::All => {
+                    // 深度テストするなら裏面同士の辺は最初から見えない
+                    style.depth_bias.is_none()
+                        || front_facing[edge.faces.0]
+                        || edge.faces.1.is_some_and(|f| front_facing[f])
+                }
+                EdgeSelection::Feature { crease_angle } => {
+                    is_feature_edge(&edge, &front_facing, crease_angle)
+                }
+            };
+            if !visible {
+                continue;
+            }
+
+            let [a, b] = edge.vertices.map(|i| clip[i]);
+            let Some((a, b)) = clip_line_near(a, b) else {
+                continue;
+            };
+
+            let face = match edge.faces.1 {
+                Some(other) if !front_facing[edge.faces.0] => other,
+                _ => edge.faces.0,
+            };
+            let color = style.color.unwrap_or(self.triangles[face].color);
+
+            let a = ScreenVertex::from_clip(a, width as f32, height as f32);
+            let b = ScreenVertex::from_clip(b, width as f32, height as f32);
+            let mut plot = |fragment: &LineFragment| {
+                let (x, y) = (fragment.x, fragment.y);
+                if style
+                    .depth_bias
+                    .is_some_and(|bias| !canvas.passes_depth(x, y, fragment.depth - bias))
+                {
+                    return;
+                }
+
+                let mut color = color;
+                if let Some(cue) = &style.depth_cue {
+                    color = cue.apply(color, fragment.w);
+                }
+                if let Some(fog) = &style.fog {
+                    color = fog.apply(color, fragment.w);
+                }
+
+                if style.anti_aliased {
+                    blend_pixel(x, y, fragment.coverage, canvas, color);
+                } else {
+                    pixels.push(ColoredCoord {
+                        x,
+                        y,
+                        r: color.r,
+                        g: color.g,
+                        b: color.b,
+                    });
+                }
+            };
+            if style.anti_aliased {
+                rasterize_line_aa(a, b, width, height, &mut plot);
+            } else {
+                rasterize_line(a, b, width, height, &mut plot);
+            }
+        }
+    }
+
+    // トゥーンの輪郭線。法線が不連続な辺 (シルエットと折り目) を Bresenham の draw_line で描き、
+    // 深度が不連続で手前の面に隠れるピクセルは深度バッファと比べて捨てる
+    pub fn render_outlines(
         &self,
         canvas: &Canvas,
-        view_projection: &Mat4,
-        pixels: &mut Vec<ColoredCoord>
+        clip: &[[f32; 4]],
+        settings: &ToonSettings,
+        fog: Option<Fog>,
+        pixels: &mut Vec<ColoredCoord>,
     ) {
-        let model_matrix = self.transform.to_matrix();
-        let mvp = view_projection.multiply(&model_matrix);
+        let front_facing: Vec<bool> = self
+            .triangles
+            .iter()
+            .map(|t| !is_back_facing(&t.vertices.map(|i| clip[i])))
+            .collect();
 
-        for triangle in &self.triangles {
-            let v0 = &self.vertices[triangle.vertices[0]];
-            let v1 = &self.vertices[triangle.vertices[1]];
-            let v2 = &self.vertices[triangle.vertices[2]];
-
-            // Back-face culling
-            let world_v0 = model_matrix.transform_point(v0.position);
-            let world_v1 = model_matrix.transform_point(v1.position);
-            let world_v2 = model_matrix.transform_point(v2.position);
-
-            let edge1 = Vec3::new(
-                world_v1.x - world_v0.x,
-                world_v1.y - world_v0.y,
-                world_v1.z - world_v0.z,
-            );
-            let edge2 = Vec3::new(
-                world_v2.x - world_v0.x,
-                world_v2.y - world_v0.y,
-                world_v2.z - world_v0.z,
+        let (width, height) = (canvas.width as f32, canvas.height as f32);
+        for edge in find_edges(self) {
+            if !is_feature_edge(&edge, &front_facing, settings.crease_angle) {
+                continue;
+            }
+
+            let [a, b] = edge.vertices.map(|i| clip[i]);
+            let Some((a, b)) = clip_line_near(a, b) else {
+                continue;
+            };
+            let a = ScreenVertex::from_clip(a, width, height);
+            let b = ScreenVertex::from_clip(b, width, height);
+            let Some((t0, t1)) = clip_segment_to_viewport(a.position, b.position, width, height)
+            else {
+                continue;
+            };
+
+            // 深度と 1/w は画面上で線形に補間できる
+            let at = |t: f32| {
+                let lerp = |p: f32, q: f32| p + (q - p) * t;
+                ScreenVertex {
+                    position: Vec2::new(
+                        lerp(a.position.x, b.position.x),
+                        lerp(a.position.y, b.position.y),
+                    ),
+                    depth: lerp(a.depth, b.depth),
+                    inv_w: lerp(a.inv_w, b.inv_w),
+                }
+            };
+            let (start, end) = (at(t0), at(t1));
+
+            let first = pixels.len();
+            draw_line(
+                start.position.x as i32,
+                start.position.y as i32,
+                end.position.x as i32,
+                end.position.y as i32,
+                canvas,
+                settings.outline_color,
+                pixels,
             );
 
-            let normal = edge1.cross(&edge2);
-            let view_dir = Vec3::new(0.0, 0.0, 1.0); // Simplified view direction
+            let delta = end.position - start.position;
+            let length_squared = delta.dot(&delta).max(f32::EPSILON);
+            let mut kept = first;
+            for i in first..pixels.len() {
+                let mut pixel = pixels[i];
+                let center = Vec2::new(pixel.x as f32 + 0.5, pixel.y as f32 + 0.5);
+                let t = ((center - start.position).dot(&delta) / length_squared).clamp(0.0, 1.0);
+                let depth = start.depth + (end.depth - start.depth) * t;
+                if !canvas.passes_depth(pixel.x, pixel.y, depth - settings.depth_bias) {
+                    continue;
+                }
 
-            // Skip back-facing triangles
-            if normal.dot(&view_dir) < 0.0 {
-                continue;
+                if let Some(fog) = &fog {
+                    let inv_w = start.inv_w + (end.inv_w - start.inv_w) * t;
+                    let color = fog.apply(settings.outline_color, 1.0 / inv_w);
+                    (pixel.r, pixel.g, pixel.b) = (color.r, color.g, color.b);
+                }
+                pixels[kept] = pixel;
+                kept += 1;
             }
+            pixels.truncate(kept);
+        }
+    }
+}
+
+// 頂点ステージを済ませたメッシュ。三角形を 1 枚ずつ好きな順で描ける
+// (半透明の三角形をメッシュをまたいで奥から順に描くため)
+pub struct ShadedMesh<'a, S: Shader> {
+    mesh: &'a Mesh,
+    shader: &'a S,
+    uniforms: Uniforms<'a>,
+    clip: &'a [[f32; 4]],
+    varyings: Vec<S::Varyings>,
+    fog: Option<Fog>,
+}
+
+impl<'a, S: Shader> ShadedMesh<'a, S> {
+    // clip はクリップ座標を書き込む作業用のバッファ。呼び出し側でフレームをまたいで使い回す
+    pub fn new(
+        mesh: &'a Mesh,
+        model: &Mat4,
+        view_projection: &Mat4,
+        camera_position: Vec3,
+        shader: &'a S,
+        clip: &'a mut Vec<[f32; 4]>,
+    ) -> Self {
+        let uniforms = Uniforms {
+            model: *model,
+            normal_matrix: model.normal_matrix(),
+            mvp: view_projection.multiply(model),
+            camera_position,
+            texture: mesh.texture.as_deref(),
+            sampler: mesh.sampler,
+            shadows: &[],
+        };
+
+        // 頂点ステージは共有頂点ごとに 1 回だけ実行する
+        shader.positions(&mesh.vertices, &uniforms, clip);
+        let varyings = mesh
+            .vertices
+            .iter()
+            .map(|vertex| shader.vertex(vertex, &uniforms))
+            .collect();
+
+        Self {
+            mesh,
+            shader,
+            uniforms,
+            clip,
+            varyings,
+            fog: None,
+        }
+    }
+
+    // シェーダの出力に視点からの距離でフォグをかける
+    pub fn with_fog(mut self, fog: Option<Fog>) -> Self {
+        self.fog = fog;
+        self
+    }
+
+    // フラグメントステージでライトごとのシャドウマップを引けるようにする
+    pub fn with_shadows(mut self, shadows: &'a [Option<ShadowMap>]) -> Self {
+        self.uniforms.shadows = shadows;
+        self
+    }
+
+    pub fn draw(&self, canvas: &mut Canvas) {
+        for index in 0..self.mesh.triangles.len() {
+            self.draw_triangle(canvas, index, Transparency::Sorted);
+        }
+    }
 
-            let p0 = mvp.project_to_screen(v0.position, canvas.width as f32, canvas.height as f32);
-            let p1 = mvp.project_to_screen(v1.position, canvas.width as f32, canvas.height as f32);
-            let p2 = mvp.project_to_screen(v2.position, canvas.width as f32, canvas.height as f32);
+    pub fn mesh(&self) -> &Mesh {
+        self.mesh
+    }
+
+    // 三角形の視点からの距離 (クリップ座標 w の平均)。奥から順に並べるのに使う
+    pub fn triangle_distance(&self, index: usize) -> f32 {
+        let [i0, i1, i2] = self.mesh.triangles[index].vertices;
+        (self.clip[i0][3] + self.clip[i1][3] + self.clip[i2][3]) / 3.0
+    }
+
+    pub fn draw_triangle(&self, canvas: &mut Canvas, index: usize, transparency: Transparency) {
+        let triangle = &self.mesh.triangles[index];
+        let clip = triangle.vertices.map(|i| self.clip[i]);
 
-            draw_triangle_filled(p0, p1, p2, canvas, triangle.color, pixels);
+        // Back-face culling
+        if is_back_facing(&clip) {
+            return;
         }
+
+        // 半透明は深度を書かずに合成する。MSAA の resolve 後に描くのでピクセル単位
+        let transparent = self.mesh.is_transparent();
+        // MSAA ではサンプルごとに深度テストし、シェーディングはピクセルに 1 回だけ行う
+        let msaa = canvas.samples() > 1 && !transparent;
+        let samples = if msaa {
+            sample_pattern(canvas.samples())
+        } else {
+            &CENTER_SAMPLE
+        };
+
+        let material = self.mesh.material_of(triangle);
+        let face_color = triangle.color.modulate(&material.base_color);
+
+        let (width, height) = (canvas.width, canvas.height);
+        let varyings = triangle.vertices.map(|i| self.varyings[i]);
+        rasterize_clipped_sampled(clip, width, height, samples, |quad| {
+            let lanes = quad
+                .fragments
+                .map(|fragment| S::Varyings::interpolate(&varyings, &fragment));
+
+            // 深度は画面上で線形なのでブロック内の差分からサンプル位置の値を求められる
+            let depth_dx = quad.fragments[1].depth - quad.fragments[0].depth;
+            let depth_dy = quad.fragments[2].depth - quad.fragments[0].depth;
+
+            for (lane, fragment) in quad.fragments.iter().enumerate() {
+                if !quad.coverage[lane] {
+                    continue;
+                }
+
+                let mut passed = 0u32;
+                if msaa {
+                    for (sample, &(sx, sy)) in samples.iter().enumerate() {
+                        let depth = fragment.depth + depth_dx * sx + depth_dy * sy;
+                        if quad.sample_mask[lane] & (1 << sample) != 0
+                            && canvas.passes_sample_depth(fragment.x, fragment.y, sample, depth)
+                        {
+                            passed |= 1 << sample;
+                        }
+                    }
+                    if passed == 0 {
+                        continue;
+                    }
+                } else if !canvas.passes_depth(fragment.x, fragment.y, fragment.depth) {
+                    continue;
+                }
+
+                let input = FragmentInput {
+                    varyings: &lanes[lane],
+                    face_color,
+                    material,
+                    quad: &lanes,
+                };
+                let Some(mut color) = self.shader.fragment(&input, &self.uniforms) else {
+                    continue;
+                };
+                if let Some(fog) = &self.fog {
+                    color = fog.apply(color, fragment.w);
+                }
+                let rgb = (color.r, color.g, color.b);
+
+                if transparent {
+                    let (blend_mode, opacity) = (self.mesh.blend_mode, self.mesh.opacity);
+                    if blend_mode == BlendMode::Alpha
+                        && transparency == Transparency::WeightedBlended
+                    {
+                        let (x, y) = (fragment.x, fragment.y);
+                        canvas.accumulate_transparent(x, y, rgb, opacity, fragment.w);
+                    } else {
+                        canvas.blend_pixel(fragment.x, fragment.y, rgb, opacity, blend_mode);
+                    }
+                } else if msaa {
+                    for (sample, &(sx, sy)) in samples.iter().enumerate() {
+                        if passed & (1 << sample) != 0 {
+                            let depth = fragment.depth + depth_dx * sx + depth_dy * sy;
+                            canvas.set_sample(fragment.x, fragment.y, sample, depth, rgb);
+                        }
+                    }
+                } else {
+                    canvas.set_depth(fragment.x, fragment.y, fragment.depth);
+                    canvas.set_pixel(fragment.x, fragment.y, color.r, color.g, color.b);
+                }
+            }
+        });
     }
 }
 
@@ -212,4 +676,59 @@ impl Default for Mesh {
     fn default() -> Self {
         Self::new()
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::camera::Camera;
+
+    const SIZE: usize = 32;
+
+    // 正面から見た立方体のクリップ座標
+    fn front_view(mesh: &Mesh) -> Vec<[f32; 4]> {
+        let mut camera = Camera::new(SIZE as f32, SIZE as f32);
+        camera.set_position(Vec3::new(0.0, 0.0, 3.0));
+        camera.look_at(Vec3::new(0.0, 0.0, 0.0));
+        let view_projection = camera.get_view_projection_matrix();
+        mesh.vertices
+            .iter()
+            .map(|v| view_projection.transform_homogeneous(v.position))
+            .collect()
+    }
+
+    #[test]
+    fn toon_outlines_trace_silhouette_and_respect_depth() {
+        let cube = Mesh::create_cube(1.0);
+        let clip = front_view(&cube);
+        let settings = ToonSettings::default();
+
+        // 立方体自身の深度では隠れず、正面の四角形の縁だけに線が引かれる (対角線は引かない)
+        let mut canvas = Canvas::new(SIZE, SIZE);
+        cube.render_depth(&mut canvas, &clip);
+        let mut pixels = Vec::new();
+        cube.render_outlines(&canvas, &clip, &settings, None, &mut pixels);
+        assert!(!pixels.is_empty());
+
+        let min_x = pixels.iter().map(|p| p.x).min().unwrap();
+        let max_x = pixels.iter().map(|p| p.x).max().unwrap();
+        let min_y = pixels.iter().map(|p| p.y).min().unwrap();
+        let max_y = pixels.iter().map(|p| p.y).max().unwrap();
+        for p in &pixels {
+            let on_border = p.x == min_x || p.x == max_x || p.y == min_y || p.y == max_y;
+            assert!(on_border, "({}, {}) is inside the silhouette", p.x, p.y);
+            assert_eq!((p.r, p.g, p.b), (0, 0, 0));
+        }
+        let mut unique: Vec<(i32, i32)> = pixels.iter().map(|p| (p.x, p.y)).collect();
+        unique.sort();
+        unique.dedup();
+        let perimeter = 2 * (max_x - min_x + max_y - min_y) as usize;
+        assert_eq!(unique.len(), perimeter);
+
+        // 手前にほかの面があれば輪郭線は隠れる
+        let mut canvas = Canvas::new(SIZE, SIZE);
+        canvas.depth.fill(0.0);
+        let mut pixels = Vec::new();
+        cube.render_outlines(&canvas, &clip, &settings, None, &mut pixels);
+        assert!(pixels.is_empty());
+    }
+}
@@ -1,8 +1,21 @@
 pub mod camera;
+pub mod edges;
 pub mod canvas;
 pub mod geometry;
+pub mod image;
+pub mod light;
+pub mod material;
 pub mod matrix;
 pub mod mesh;
+pub mod postprocess;
+pub mod raster;
+pub mod recorder;
 pub mod renderer;
+pub mod scene_graph;
+pub mod shader;
+pub mod shadow;
+pub mod ssao;
+pub mod texture;
+pub mod vertex_batch;
 
 pub use canvas::*;
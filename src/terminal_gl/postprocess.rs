@@ -0,0 +1,521 @@
+use crate::geometry::Color;
+use crate::terminal_gl::Canvas;
+use std::fs;
+use std::io::{self, ErrorKind};
+use std::path::Path;
+use std::rc::Rc;
+
+// ラスタライズの後、端末に出力する前にキャンバスの r / g / b 全体にかける効果
+#[derive(Clone, Debug)]
+pub enum PostEffect {
+    // 出力 = 入力^(1 / gamma)
+    Gamma(f32),
+    // 露出をかけてから 1 - exp(-x) で白飛びをなだらかにする
+    ToneMap {
+        exposure: f32,
+    },
+    // brightness は -1..1 の加算、contrast は 0.5 を中心にした倍率
+    BrightnessContrast {
+        brightness: f32,
+        contrast: f32,
+    },
+    // 中心から radius (中心 0, 隅 1) より外側を strength まで暗くする
+    Vignette {
+        strength: f32,
+        radius: f32,
+    },
+    // 明るさが threshold を超える部分をぼかして足す
+    Bloom {
+        threshold: u8,
+        radius: usize,
+        intensity: f32,
+    },
+    // 明るさの勾配 (Sobel) が threshold を超える所を color で描く
+    EdgeDetect {
+        threshold: f32,
+        color: Color,
+    },
+    // ブラウン管風に 1 行おきに暗くし、列ごとに RGB の蛍光体を強調する
+    Scanlines {
+        darkness: f32,
+    },
+    // 3D LUT による色の置き換え
+    ColorGrade(Rc<Lut3d>),
+}
+
+impl PostEffect {
+    pub fn apply(&self, canvas: &mut Canvas) {
+        match self {
+            PostEffect::Gamma(gamma) => {
+                let inverse = 1.0 / gamma.max(1e-3);
+                map_channels(canvas, |v| v.powf(inverse));
+            }
+            // 露出 0 の極限は恒等変換なので、0 以下では何もしない (そのままでは 0 / 0 になる)
+            PostEffect::ToneMap { exposure } if *exposure <= 0.0 => {}
+            PostEffect::ToneMap { exposure } => {
+                let white = 1.0 - (-exposure).exp();
+                map_channels(canvas, |v| (1.0 - (-v * exposure).exp()) / white);
+            }
+            PostEffect::BrightnessContrast {
+                brightness,
+                contrast,
+            } => {
+                map_channels(canvas, |v| (v - 0.5) * contrast + 0.5 + brightness);
+            }
+            PostEffect::Vignette { strength, radius } => vignette(canvas, *strength, *radius),
+            PostEffect::Bloom {
+                threshold,
+                radius,
+                intensity,
+            } => bloom(canvas, *threshold, *radius, *intensity),
+            PostEffect::EdgeDetect { threshold, color } => edge_detect(canvas, *threshold, *color),
+            PostEffect::Scanlines { darkness } => scanlines(canvas, *darkness),
+            PostEffect::ColorGrade(lut) => {
+                for i in 0..canvas.width * canvas.height {
+                    let graded = lut.sample(Color::new(canvas.r[i], canvas.g[i], canvas.b[i]));
+                    canvas.r[i] = graded.r;
+                    canvas.g[i] = graded.g;
+                    canvas.b[i] = graded.b;
+                }
+            }
+        }
+    }
+}
+
+// 効果を順にかける
+pub fn apply_effects(canvas: &mut Canvas, effects: &[PostEffect]) {
+    for effect in effects {
+        effect.apply(canvas);
+    }
+}
+
+fn to_u8(v: f32) -> u8 {
+    (v * 255.0 + 0.5).clamp(0.0, 255.0) as u8
+}
+
+// 0..1 の値の変換を 256 段の表にしてから全チャンネルにかける
+fn map_channels(canvas: &mut Canvas, f: impl Fn(f32) -> f32) {
+    let table: [u8; 256] = std::array::from_fn(|i| to_u8(f(i as f32 / 255.0)));
+    for plane in [&mut canvas.r, &mut canvas.g, &mut canvas.b] {
+        for v in plane.iter_mut() {
+            *v = table[*v as usize];
+        }
+    }
+}
+
+fn luminance(r: u8, g: u8, b: u8) -> f32 {
+    (0.299 * r as f32 + 0.587 * g as f32 + 0.114 * b as f32) / 255.0
+}
+
+fn vignette(canvas: &mut Canvas, strength: f32, radius: f32) {
+    let (width, height) = (canvas.width, canvas.height);
+    for y in 0..height {
+        for x in 0..width {
+            let dx = (x as f32 + 0.5) / width as f32 * 2.0 - 1.0;
+            let dy = (y as f32 + 0.5) / height as f32 * 2.0 - 1.0;
+            let distance = (dx * dx + dy * dy).sqrt() / std::f32::consts::SQRT_2;
+            let t = ((distance - radius) / (1.0 - radius).max(1e-3)).clamp(0.0, 1.0);
+            let factor = 1.0 - strength * t * t * (3.0 - 2.0 * t);
+
+            let idx = y * width + x;
+            canvas.r[idx] = (canvas.r[idx] as f32 * factor) as u8;
+            canvas.g[idx] = (canvas.g[idx] as f32 * factor) as u8;
+            canvas.b[idx] = (canvas.b[idx] as f32 * factor) as u8;
+        }
+    }
+}
+
+// 横・縦に分けた箱型ぼかし
+fn box_blur(plane: &mut [f32], width: usize, height: usize, radius: usize) {
+    let mut scratch = vec![0.0; plane.len()];
+    for y in 0..height {
+        for x in 0..width {
+            let range = x.saturating_sub(radius)..(x + radius + 1).min(width);
+            let count = range.len() as f32;
+            scratch[y * width + x] = range.map(|sx| plane[y * width + sx]).sum::<f32>() / count;
+        }
+    }
+    for y in 0..height {
+        for x in 0..width {
+            let range = y.saturating_sub(radius)..(y + radius + 1).min(height);
+            let count = range.len() as f32;
+            plane[y * width + x] = range.map(|sy| scratch[sy * width + x]).sum::<f32>() / count;
+        }
+    }
+}
+
+fn bloom(canvas: &mut Canvas, threshold: u8, radius: usize, intensity: f32) {
+    let (width, height) = (canvas.width, canvas.height);
+    let threshold = threshold as f32 / 255.0;
+
+    // しきい値を超えた分だけを取り出し、2 回ぼかして山なりに広げる
+    let scale: Vec<f32> = (0..width * height)
+        .map(|i| {
+            let excess = luminance(canvas.r[i], canvas.g[i], canvas.b[i]) - threshold;
+            excess.max(0.0) / (1.0 - threshold).max(1e-3)
+        })
+        .collect();
+    let mut planes = [&canvas.r, &canvas.g, &canvas.b].map(|plane| {
+        plane
+            .iter()
+            .zip(&scale)
+            .map(|(&v, s)| v as f32 * s)
+            .collect::<Vec<_>>()
+    });
+    for plane in planes.iter_mut() {
+        box_blur(plane, width, height, radius);
+        box_blur(plane, width, height, radius);
+    }
+
+    for (plane, glow) in [&mut canvas.r, &mut canvas.g, &mut canvas.b]
+        .into_iter()
+        .zip(&planes)
+    {
+        for (v, g) in plane.iter_mut().zip(glow) {
+            *v = (*v as f32 + g * intensity).clamp(0.0, 255.0) as u8;
+        }
+    }
+}
+
+fn edge_detect(canvas: &mut Canvas, threshold: f32, color: Color) {
+    let (width, height) = (canvas.width, canvas.height);
+    let lum: Vec<f32> = (0..width * height)
+        .map(|i| luminance(canvas.r[i], canvas.g[i], canvas.b[i]))
+        .collect();
+    let at = |x: usize, y: usize, dx: isize, dy: isize| {
+        let sx = (x as isize + dx).clamp(0, width as isize - 1) as usize;
+        let sy = (y as isize + dy).clamp(0, height as isize - 1) as usize;
+        lum[sy * width + sx]
+    };
+
+    for y in 0..height {
+        for x in 0..width {
+            let gx = at(x, y, 1, -1) + 2.0 * at(x, y, 1, 0) + at(x, y, 1, 1)
+                - at(x, y, -1, -1)
+                - 2.0 * at(x, y, -1, 0)
+                - at(x, y, -1, 1);
+            let gy = at(x, y, -1, 1) + 2.0 * at(x, y, 0, 1) + at(x, y, 1, 1)
+                - at(x, y, -1, -1)
+                - 2.0 * at(x, y, 0, -1)
+                - at(x, y, 1, -1);
+            let magnitude = (gx * gx + gy * gy).sqrt();
+            if magnitude > threshold {
+                let idx = y * width + x;
+                // しきい値の 2 倍で完全に線の色にする
+                let t = ((magnitude - threshold) / threshold.max(1e-3)).min(1.0);
+                let mixed = Color::new(canvas.r[idx], canvas.g[idx], canvas.b[idx]).lerp(&color, t);
+                canvas.r[idx] = mixed.r;
+                canvas.g[idx] = mixed.g;
+                canvas.b[idx] = mixed.b;
+            }
+        }
+    }
+}
+
+fn scanlines(canvas: &mut Canvas, darkness: f32) {
+    let (width, height) = (canvas.width, canvas.height);
+    // 蛍光体の並び。自分の色はそのまま、ほかの 2 色を少し落とす
+    let mask = [[1.0, 0.8, 0.8], [0.8, 1.0, 0.8], [0.8, 0.8, 1.0]];
+    for y in 0..height {
+        let row = if y % 2 == 1 { 1.0 - darkness } else { 1.0 };
+        for x in 0..width {
+            let idx = y * width + x;
+            let [mr, mg, mb] = mask[x % 3];
+            canvas.r[idx] = (canvas.r[idx] as f32 * row * mr) as u8;
+            canvas.g[idx] = (canvas.g[idx] as f32 * row * mg) as u8;
+            canvas.b[idx] = (canvas.b[idx] as f32 * row * mb) as u8;
+        }
+    }
+}
+
+// 一辺の格子数の上限。.cube の一般的な大きさ (17, 33, 65) より十分大きくとる
+pub const MAX_LUT_SIZE: usize = 256;
+
+// size^3 の格子に出力色を持つ 3D LUT。格子の間はトリリニア補間する
+#[derive(Clone, Debug)]
+pub struct Lut3d {
+    size: usize,
+    // r が最も速く変わる順 (.cube と同じ)
+    table: Vec<[f32; 3]>,
+}
+
+impl Lut3d {
+    // f には 0..1 の入力色を渡し、0..1 の出力色を返させる
+    pub fn from_fn(size: usize, f: impl Fn([f32; 3]) -> [f32; 3]) -> Self {
+        let size = size.clamp(2, MAX_LUT_SIZE);
+        let scale = 1.0 / (size - 1) as f32;
+        let mut table = Vec::with_capacity(size * size * size);
+        for b in 0..size {
+            for g in 0..size {
+                for r in 0..size {
+                    table.push(f([r as f32 * scale, g as f32 * scale, b as f32 * scale]));
+                }
+            }
+        }
+        Self { size, table }
+    }
+
+    // Adobe の .cube 形式 (LUT_3D_SIZE と 0..1 の "r g b" の行) を読み込む
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let text = fs::read_to_string(path)?;
+        Self::parse_cube(&text)
+    }
+
+    pub fn parse_cube(text: &str) -> io::Result<Self> {
+        let invalid = |message: &str| io::Error::new(ErrorKind::InvalidData, message.to_string());
+        let mut size = None;
+        let mut table = Vec::new();
+        for line in text.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let mut fields = line.split_whitespace();
+            match fields.next() {
+                Some("LUT_3D_SIZE") => {
+                    let value = fields.next().and_then(|v| v.parse::<usize>().ok());
+                    size = Some(value.ok_or_else(|| invalid("bad LUT_3D_SIZE"))?);
+                }
+                Some(first) if first.starts_with(|c: char| c.is_ascii_digit() || c == '-') => {
+                    let values: Vec<f32> = line
+                        .split_whitespace()
+                        .map(|v| v.parse::<f32>())
+                        .collect::<Result<_, _>>()
+                        .map_err(|_| invalid("bad LUT entry"))?;
+                    if values.len() != 3 {
+                        return Err(invalid("LUT entry must have 3 values"));
+                    }
+                    table.push([values[0], values[1], values[2]]);
+                }
+                // TITLE や DOMAIN_MIN などは使わない
+                _ => {}
+            }
+        }
+
+        let size = size.ok_or_else(|| invalid("missing LUT_3D_SIZE"))?;
+        if !(2..=MAX_LUT_SIZE).contains(&size) {
+            return Err(invalid("LUT_3D_SIZE out of range"));
+        }
+        if Some(table.len()) != size.checked_mul(size).and_then(|n| n.checked_mul(size)) {
+            return Err(invalid("LUT size does not match the number of entries"));
+        }
+        Ok(Self { size, table })
+    }
+
+    pub fn sample(&self, color: Color) -> Color {
+        let max = (self.size - 1) as f32;
+        let coordinate = |v: u8| {
+            let p = v as f32 / 255.0 * max;
+            let i = (p.floor() as usize).min(self.size - 2);
+            (i, p - i as f32)
+        };
+        let (r0, fr) = coordinate(color.r);
+        let (g0, fg) = coordinate(color.g);
+        let (b0, fb) = coordinate(color.b);
+
+        let mut out = [0.0; 3];
+        for (db, wb) in [(0, 1.0 - fb), (1, fb)] {
+            for (dg, wg) in [(0, 1.0 - fg), (1, fg)] {
+                for (dr, wr) in [(0, 1.0 - fr), (1, fr)] {
+                    let index = ((b0 + db) * self.size + g0 + dg) * self.size + r0 + dr;
+                    let w = wr * wg * wb;
+                    for (o, v) in out.iter_mut().zip(self.table[index]) {
+                        *o += v * w;
+                    }
+                }
+            }
+        }
+        Color::new(to_u8(out[0]), to_u8(out[1]), to_u8(out[2]))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 左半分が暗く右半分が明るい、行ごとに少しずつ色の違う画像
+    fn test_canvas(width: usize, height: usize) -> Canvas {
+        let mut canvas = Canvas::new(width, height);
+        for y in 0..height {
+            for x in 0..width {
+                let v = if x < width / 2 { 40 } else { 200 };
+                canvas.set_pixel(x as i32, y as i32, v, (v as usize + y * 3) as u8, 255 - v);
+            }
+        }
+        canvas
+    }
+
+    fn uniform_canvas(width: usize, height: usize, v: u8) -> Canvas {
+        let mut canvas = Canvas::new(width, height);
+        canvas.r.fill(v);
+        canvas.g.fill(v);
+        canvas.b.fill(v);
+        canvas
+    }
+
+    fn pixels(canvas: &Canvas) -> Vec<(u8, u8, u8)> {
+        (0..canvas.width * canvas.height)
+            .map(|i| (canvas.r[i], canvas.g[i], canvas.b[i]))
+            .collect()
+    }
+
+    #[test]
+    fn gamma_one_is_identity() {
+        let mut canvas = test_canvas(16, 8);
+        let before = pixels(&canvas);
+        PostEffect::Gamma(1.0).apply(&mut canvas);
+        assert_eq!(pixels(&canvas), before);
+    }
+
+    #[test]
+    fn identity_lut_is_identity() {
+        let mut canvas = Canvas::new(256, 3);
+        for x in 0..256 {
+            canvas.set_pixel(x, 0, x as u8, 0, 255);
+            canvas.set_pixel(x, 1, 255 - x as u8, x as u8, 128);
+            canvas.set_pixel(x, 2, 7, 255 - x as u8, x as u8);
+        }
+        let before = pixels(&canvas);
+        for size in [2, 17, 33] {
+            PostEffect::ColorGrade(Rc::new(Lut3d::from_fn(size, |rgb| rgb))).apply(&mut canvas);
+            assert_eq!(pixels(&canvas), before, "size {size}");
+        }
+    }
+
+    #[test]
+    fn tone_map_without_exposure_is_identity() {
+        for exposure in [0.0, -1.0] {
+            let mut canvas = test_canvas(16, 8);
+            let before = pixels(&canvas);
+            PostEffect::ToneMap { exposure }.apply(&mut canvas);
+            assert_eq!(pixels(&canvas), before);
+        }
+    }
+
+    #[test]
+    fn tone_map_keeps_black_and_white() {
+        let mut canvas = Canvas::new(2, 1);
+        canvas.set_pixel(1, 0, 255, 255, 255);
+        PostEffect::ToneMap { exposure: 2.0 }.apply(&mut canvas);
+        assert_eq!(pixels(&canvas), vec![(0, 0, 0), (255, 255, 255)]);
+    }
+
+    #[test]
+    fn vignette_leaves_center_alone() {
+        let mut canvas = uniform_canvas(40, 20, 200);
+        PostEffect::Vignette {
+            strength: 0.8,
+            radius: 0.3,
+        }
+        .apply(&mut canvas);
+        let at = |x: usize, y: usize| canvas.r[y * canvas.width + x];
+        assert_eq!(at(20, 10), 200);
+        assert_eq!(at(19, 9), 200);
+        assert!(at(0, 0) < 100);
+        assert!(at(39, 19) < 100);
+    }
+
+    #[test]
+    fn bloom_only_spreads_above_threshold() {
+        let bloom = PostEffect::Bloom {
+            threshold: 180,
+            radius: 2,
+            intensity: 1.0,
+        };
+
+        // しきい値以下だけの画像は変わらない
+        let mut canvas = uniform_canvas(20, 20, 150);
+        canvas.set_pixel(10, 10, 180, 180, 180);
+        let before = pixels(&canvas);
+        bloom.apply(&mut canvas);
+        assert_eq!(pixels(&canvas), before);
+
+        // 明るい点の周りは明るくなり、ぼかしの届かない所は変わらない
+        let mut canvas = uniform_canvas(20, 20, 20);
+        canvas.set_pixel(10, 10, 255, 255, 255);
+        bloom.apply(&mut canvas);
+        let at = |x: usize, y: usize| canvas.r[y * canvas.width + x];
+        assert!(at(11, 10) > 20);
+        assert!(at(10, 12) > 20);
+        assert_eq!(at(0, 0), 20);
+        assert_eq!(at(10, 16), 20);
+    }
+
+    #[test]
+    fn edge_detect_only_marks_step_edge() {
+        let (width, height) = (16, 8);
+        let mut canvas = Canvas::new(width, height);
+        for y in 0..height {
+            for x in 0..width {
+                let v = if x < width / 2 { 30 } else { 220 };
+                canvas.set_pixel(x as i32, y as i32, v, v, v);
+            }
+        }
+        let color = Color::new(255, 0, 0);
+        PostEffect::EdgeDetect {
+            threshold: 0.5,
+            color,
+        }
+        .apply(&mut canvas);
+
+        for y in 0..height {
+            for x in 0..width {
+                let idx = y * width + x;
+                let pixel = (canvas.r[idx], canvas.g[idx], canvas.b[idx]);
+                if x == width / 2 - 1 || x == width / 2 {
+                    assert_eq!(pixel, (255, 0, 0), "({x}, {y})");
+                } else {
+                    let v = if x < width / 2 { 30 } else { 220 };
+                    assert_eq!(pixel, (v, v, v), "({x}, {y})");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn scanlines_only_darken_odd_rows() {
+        let (width, height) = (6, 6);
+        let mut canvas = uniform_canvas(width, height, 200);
+        PostEffect::Scanlines { darkness: 0.5 }.apply(&mut canvas);
+        for y in 0..height {
+            for x in 0..width {
+                let idx = y * width + x;
+                let brightest = canvas.r[idx].max(canvas.g[idx]).max(canvas.b[idx]);
+                // 蛍光体のマスクで各画素の 1 色だけは元の明るさのまま残る
+                let expected = if y % 2 == 1 { 100 } else { 200 };
+                assert_eq!(brightest, expected, "({x}, {y})");
+            }
+        }
+    }
+
+    #[test]
+    fn parse_cube_reads_entries() {
+        let mut text = String::from("TITLE \"test\"\n# comment\nLUT_3D_SIZE 2\n");
+        for b in 0..2 {
+            for g in 0..2 {
+                for r in 0..2 {
+                    text.push_str(&format!("{r} {g} {b}\n"));
+                }
+            }
+        }
+        let lut = Lut3d::parse_cube(&text).unwrap();
+        assert_eq!(lut.size, 2);
+        let sampled = lut.sample(Color::new(10, 128, 250));
+        assert_eq!((sampled.r, sampled.g, sampled.b), (10, 128, 250));
+    }
+
+    #[test]
+    fn parse_cube_rejects_bad_sizes() {
+        let huge = format!("LUT_3D_SIZE {}\n0 0 0\n", usize::MAX);
+        for text in [
+            "LUT_3D_SIZE 3000000\n0 0 0\n",
+            huge.as_str(),
+            "LUT_3D_SIZE 1\n0 0 0\n",
+            "LUT_3D_SIZE 2\n0 0 0\n",
+            "0 0 0\n",
+        ] {
+            let error = Lut3d::parse_cube(text).unwrap_err();
+            assert_eq!(error.kind(), ErrorKind::InvalidData);
+        }
+    }
+}
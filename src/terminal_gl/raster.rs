@@ -0,0 +1,893 @@
+use crate::geometry::{Color, Vec2, Vec3};
+
+// エッジ関数 (half-space) による三角形ラスタライザ
+//
+// 頂点座標は 8 ビットのサブピクセル精度を持つ固定小数点に丸め、
+// ピクセル中心 (x + 0.5, y + 0.5) でサンプリングする。
+// 辺の上にちょうど乗ったピクセルは top-left ルールで一方の三角形にだけ属するので、
+// 辺を共有する三角形同士で重なりも隙間もできない。
+
+pub const SUBPIXEL_BITS: u32 = 8;
+const SUBPIXEL_ONE: i64 = 1 << SUBPIXEL_BITS;
+const SUBPIXEL_HALF: i64 = SUBPIXEL_ONE / 2;
+
+// 固定小数点で扱える画面座標の範囲。クリップ座標の三角形はこの内側に切り取ってから渡す
+const GUARD_BAND: f32 = 16384.0;
+
+#[derive(Clone, Copy, Debug)]
+struct FixedPoint {
+    x: i64,
+    y: i64,
+}
+
+impl FixedPoint {
+    fn from_vec2(p: Vec2) -> Self {
+        Self {
+            x: (p.x * SUBPIXEL_ONE as f32).round() as i64,
+            y: (p.y * SUBPIXEL_ONE as f32).round() as i64,
+        }
+    }
+}
+
+// a -> b の辺に対する p の符号付き面積 (2 倍、サブピクセル単位の二乗)
+fn edge_function(a: FixedPoint, b: FixedPoint, p: FixedPoint) -> i64 {
+    (b.x - a.x) * (p.y - a.y) - (b.y - a.y) * (p.x - a.x)
+}
+
+// y 軸が下向きの画面座標で、内側が正になる向きの辺について
+// 上辺 (水平で右向き) と左辺 (上向き) を判定する
+fn is_top_left(a: FixedPoint, b: FixedPoint) -> bool {
+    let dx = b.x - a.x;
+    let dy = b.y - a.y;
+    (dy == 0 && dx > 0) || dy < 0
+}
+
+struct Edge {
+    // ピクセル中心での値 (ルール適用前)
+    value: i64,
+    step_x: i64,
+    step_y: i64,
+    // top-left でない辺は値 0 を外側として扱う
+    bias: i64,
+}
+
+impl Edge {
+    fn new(a: FixedPoint, b: FixedPoint, origin: FixedPoint) -> Self {
+        Self {
+            value: edge_function(a, b, origin),
+            step_x: -(b.y - a.y) * SUBPIXEL_ONE,
+            step_y: (b.x - a.x) * SUBPIXEL_ONE,
+            bias: if is_top_left(a, b) { 0 } else { -1 },
+        }
+    }
+}
+
+// 2x2 ピクセルのブロック。レーンの並びは (x, y), (x+1, y), (x, y+1), (x+1, y+1)。
+// 三角形の外側のレーン (coverage が false) も重心座標を外挿して持つので、
+// ブロック内の差分から UV などの画面空間微分を求められる。
+#[derive(Clone, Copy, Debug)]
+pub struct Quad {
+    pub x: i32,
+    pub y: i32,
+    pub coverage: [bool; 4],
+    // レーンごとに、覆われたサンプル位置のビット (サンプル i がビット i)
+    pub sample_mask: [u32; 4],
+    pub barycentric: [[f32; 3]; 4],
+}
+
+pub const QUAD_OFFSETS: [(i32, i32); 4] = [(0, 0), (1, 0), (0, 1), (1, 1)];
+
+// ピクセル中心だけをサンプリングする (MSAA なし)
+pub const CENTER_SAMPLE: [(f32, f32); 1] = [(0.0, 0.0)];
+
+// MSAA のサンプル位置 (ピクセル中心からのオフセット、ピクセル単位)。
+// 回転グリッドなので水平・垂直に近い辺でもサンプルが段差を拾う
+pub fn sample_pattern(samples: usize) -> &'static [(f32, f32)] {
+    const TWO: [(f32, f32); 2] = [(0.25, 0.25), (-0.25, -0.25)];
+    const FOUR: [(f32, f32); 4] = [
+        (-0.125, -0.375),
+        (0.375, -0.125),
+        (-0.375, 0.125),
+        (0.125, 0.375),
+    ];
+    const EIGHT: [(f32, f32); 8] = [
+        (0.0625, -0.1875),
+        (-0.0625, 0.1875),
+        (0.3125, 0.0625),
+        (-0.1875, -0.3125),
+        (-0.3125, 0.3125),
+        (-0.4375, -0.0625),
+        (0.1875, 0.4375),
+        (0.4375, -0.4375),
+    ];
+    match samples {
+        0 | 1 => &CENTER_SAMPLE,
+        2 | 3 => &TWO,
+        4..=7 => &FOUR,
+        _ => &EIGHT,
+    }
+}
+
+// 三角形が 1 ピクセルでも覆う 2x2 ブロックごとにコールバックを呼ぶ。
+// samples の位置ごとに被覆を調べ、いずれかのサンプルが覆われたピクセルを coverage とする。
+// 重心座標は (サンプルの位置に関係なく) ピクセル中心で求め、p0, p1, p2 の順で巻き方向には依存しない
+pub fn rasterize_triangle_quads_sampled<F>(
+    p0: Vec2,
+    p1: Vec2,
+    p2: Vec2,
+    width: usize,
+    height: usize,
+    samples: &[(f32, f32)],
+    mut emit: F,
+) where
+    F: FnMut(&Quad),
+{
+    let sample_offsets: Vec<(i64, i64)> = samples
+        .iter()
+        .map(|&(x, y)| {
+            (
+                (x * SUBPIXEL_ONE as f32).round() as i64,
+                (y * SUBPIXEL_ONE as f32).round() as i64,
+            )
+        })
+        .collect();
+
+    let points = [p0, p1, p2];
+    if points
+        .iter()
+        .any(|p| !(p.x.abs() < GUARD_BAND && p.y.abs() < GUARD_BAND))
+    {
+        return;
+    }
+
+    let v0 = FixedPoint::from_vec2(p0);
+    let mut v1 = FixedPoint::from_vec2(p1);
+    let mut v2 = FixedPoint::from_vec2(p2);
+
+    let mut area = edge_function(v0, v1, v2);
+    if area == 0 {
+        return;
+    }
+
+    // 内側が正になるように巻き方向をそろえる
+    let flipped = area < 0;
+    if flipped {
+        std::mem::swap(&mut v1, &mut v2);
+        area = -area;
+    }
+
+    // ピクセル単位のバウンディングボックス (画面内にクリップし、偶数座標にそろえる)
+    let min_x = (v0.x.min(v1.x).min(v2.x) >> SUBPIXEL_BITS).max(0) & !1;
+    let min_y = (v0.y.min(v1.y).min(v2.y) >> SUBPIXEL_BITS).max(0) & !1;
+    let max_x = (v0.x.max(v1.x).max(v2.x) >> SUBPIXEL_BITS).min(width as i64 - 1);
+    let max_y = (v0.y.max(v1.y).max(v2.y) >> SUBPIXEL_BITS).min(height as i64 - 1);
+    if min_x > max_x || min_y > max_y {
+        return;
+    }
+
+    let origin = FixedPoint {
+        x: min_x * SUBPIXEL_ONE + SUBPIXEL_HALF,
+        y: min_y * SUBPIXEL_ONE + SUBPIXEL_HALF,
+    };
+
+    // e0 は v0 の対辺、e1 は v1 の対辺、e2 は v2 の対辺
+    let mut edges = [
+        Edge::new(v1, v2, origin),
+        Edge::new(v2, v0, origin),
+        Edge::new(v0, v1, origin),
+    ];
+
+    let inv_area = 1.0 / area as f32;
+
+    for y in (min_y..=max_y).step_by(2) {
+        let mut row = edges.each_ref().map(|e| e.value);
+
+        for x in (min_x..=max_x).step_by(2) {
+            let mut quad = Quad {
+                x: x as i32,
+                y: y as i32,
+                coverage: [false; 4],
+                sample_mask: [0; 4],
+                barycentric: [[0.0; 3]; 4],
+            };
+
+            for (lane, &(dx, dy)) in QUAD_OFFSETS.iter().enumerate() {
+                let w = [0, 1, 2]
+                    .map(|i| row[i] + edges[i].step_x * dx as i64 + edges[i].step_y * dy as i64);
+
+                if x + (dx as i64) < width as i64 && y + (dy as i64) < height as i64 {
+                    for (sample, &(sx, sy)) in sample_offsets.iter().enumerate() {
+                        // step はピクセル単位なのでサブピクセル単位に直して足す
+                        let inside = (0..3).all(|i| {
+                            let edge = &edges[i];
+                            let value = w[i]
+                                + edge.step_x / SUBPIXEL_ONE * sx
+                                + edge.step_y / SUBPIXEL_ONE * sy;
+                            value + edge.bias >= 0
+                        });
+                        if inside {
+                            quad.sample_mask[lane] |= 1 << sample;
+                        }
+                    }
+                }
+                quad.coverage[lane] = quad.sample_mask[lane] != 0;
+
+                let b = w.map(|v| v as f32 * inv_area);
+                quad.barycentric[lane] = if flipped { [b[0], b[2], b[1]] } else { b };
+            }
+
+            if quad.coverage.iter().any(|&c| c) {
+                emit(&quad);
+            }
+
+            for (value, edge) in row.iter_mut().zip(&edges) {
+                *value += edge.step_x * 2;
+            }
+        }
+
+        for edge in edges.iter_mut() {
+            edge.value += edge.step_y * 2;
+        }
+    }
+}
+
+// 画面座標に変換した頂点。depth は [0, 1] の NDC 深度、inv_w はクリップ座標 w の逆数
+#[derive(Clone, Copy, Debug)]
+pub struct ScreenVertex {
+    pub position: Vec2,
+    pub depth: f32,
+    pub inv_w: f32,
+}
+
+impl ScreenVertex {
+    pub fn from_clip(clip: [f32; 4], width: f32, height: f32) -> Self {
+        let inv_w = 1.0 / clip[3];
+        let ndc_x = clip[0] * inv_w;
+        let ndc_y = clip[1] * inv_w;
+        let ndc_z = clip[2] * inv_w;
+        Self {
+            position: Vec2::new((ndc_x + 1.0) * width * 0.5, (1.0 - ndc_y) * height * 0.5),
+            depth: ndc_z * 0.5 + 0.5,
+            inv_w,
+        }
+    }
+}
+
+// ラスタライズで生成される 1 ピクセル分の情報
+#[derive(Clone, Copy, Debug)]
+pub struct Fragment {
+    pub x: i32,
+    pub y: i32,
+    // 画面空間で線形補間した [0, 1] の深度 (深度テスト用)
+    pub depth: f32,
+    // クリップ座標の w (透視投影ではビュー空間での距離)
+    pub w: f32,
+    // 元の三角形の頂点に対する、透視補正済みの重心座標
+    pub barycentric: [f32; 3],
+}
+
+impl Fragment {
+    pub fn interpolate(&self, values: [f32; 3]) -> f32 {
+        let [b0, b1, b2] = self.barycentric;
+        values[0] * b0 + values[1] * b1 + values[2] * b2
+    }
+
+    pub fn interpolate_vec2(&self, values: [Vec2; 3]) -> Vec2 {
+        Vec2::new(
+            self.interpolate([values[0].x, values[1].x, values[2].x]),
+            self.interpolate([values[0].y, values[1].y, values[2].y]),
+        )
+    }
+
+    pub fn interpolate_vec3(&self, values: [Vec3; 3]) -> Vec3 {
+        Vec3::new(
+            self.interpolate([values[0].x, values[1].x, values[2].x]),
+            self.interpolate([values[0].y, values[1].y, values[2].y]),
+            self.interpolate([values[0].z, values[1].z, values[2].z]),
+        )
+    }
+
+    pub fn interpolate_color(&self, values: [Color; 3]) -> Color {
+        let channel = |c: [u8; 3]| {
+            self.interpolate([c[0] as f32, c[1] as f32, c[2] as f32])
+                .round()
+                .clamp(0.0, 255.0) as u8
+        };
+        Color::new(
+            channel([values[0].r, values[1].r, values[2].r]),
+            channel([values[0].g, values[1].g, values[2].g]),
+            channel([values[0].b, values[1].b, values[2].b]),
+        )
+    }
+}
+
+// クリップで生成された頂点。barycentric は元の三角形に対する重み
+#[derive(Clone, Copy, Debug)]
+struct ClipVertex {
+    position: [f32; 4],
+    barycentric: [f32; 3],
+}
+
+impl ClipVertex {
+    fn lerp(&self, other: &ClipVertex, t: f32) -> ClipVertex {
+        let mut position = [0.0; 4];
+        for (i, p) in position.iter_mut().enumerate() {
+            *p = self.position[i] + (other.position[i] - self.position[i]) * t;
+        }
+        let mut barycentric = [0.0; 3];
+        for (i, b) in barycentric.iter_mut().enumerate() {
+            *b = self.barycentric[i] + (other.barycentric[i] - self.barycentric[i]) * t;
+        }
+        ClipVertex {
+            position,
+            barycentric,
+        }
+    }
+}
+
+// クリップ座標の三角形をニア平面 (z = -w) と x, y のガードバンドの平面で切り取る。
+// ガードバンドは画面座標が ±GUARD_BAND に収まる |x| <= kx * w, |y| <= ky * w
+fn clip_triangle(
+    clip: [[f32; 4]; 3],
+    width: usize,
+    height: usize,
+    polygon: &mut Vec<ClipVertex>,
+    scratch: &mut Vec<ClipVertex>,
+) {
+    polygon.clear();
+    polygon.extend([
+        ClipVertex {
+            position: clip[0],
+            barycentric: [1.0, 0.0, 0.0],
+        },
+        ClipVertex {
+            position: clip[1],
+            barycentric: [0.0, 1.0, 0.0],
+        },
+        ClipVertex {
+            position: clip[2],
+            barycentric: [0.0, 0.0, 1.0],
+        },
+    ]);
+
+    let kx = GUARD_BAND / width.max(1) as f32;
+    let ky = GUARD_BAND / height.max(1) as f32;
+    clip_polygon(polygon, scratch, |p| p[2] + p[3]);
+    clip_polygon(polygon, scratch, |p| kx * p[3] - p[0]);
+    clip_polygon(polygon, scratch, |p| kx * p[3] + p[0]);
+    clip_polygon(polygon, scratch, |p| ky * p[3] - p[1]);
+    clip_polygon(polygon, scratch, |p| ky * p[3] + p[1]);
+}
+
+// distance が負になる側を切り落とす (Sutherland-Hodgman)
+fn clip_polygon<D>(polygon: &mut Vec<ClipVertex>, scratch: &mut Vec<ClipVertex>, distance: D)
+where
+    D: Fn(&[f32; 4]) -> f32,
+{
+    if polygon.iter().all(|v| distance(&v.position) >= 0.0) {
+        return;
+    }
+
+    scratch.clear();
+    for i in 0..polygon.len() {
+        let current = &polygon[i];
+        let next = &polygon[(i + 1) % polygon.len()];
+        let d_current = distance(&current.position);
+        let d_next = distance(&next.position);
+
+        if d_current >= 0.0 {
+            scratch.push(*current);
+        }
+        if (d_current >= 0.0) != (d_next >= 0.0) {
+            let t = d_current / (d_current - d_next);
+            scratch.push(current.lerp(next, t));
+        }
+    }
+    std::mem::swap(polygon, scratch);
+}
+
+// 2x2 ブロック分のフラグメント。coverage が false のレーンは微分計算用
+#[derive(Clone, Copy, Debug)]
+pub struct FragmentQuad {
+    pub fragments: [Fragment; 4],
+    pub coverage: [bool; 4],
+    pub sample_mask: [u32; 4],
+}
+
+// 外側から見て反時計回りの面を表とする。
+// w の符号に関係なく判定できるようクリップ座標 (x, y, w) の行列式を使う
+pub fn is_back_facing(clip: &[[f32; 4]; 3]) -> bool {
+    let [a, b, c] = clip;
+    let det = a[0] * (b[1] * c[3] - c[1] * b[3]) - b[0] * (a[1] * c[3] - c[1] * a[3])
+        + c[0] * (a[1] * b[3] - b[1] * a[3]);
+    det <= 0.0
+}
+
+// クリップ座標の三角形をニア平面とガードバンドで切り取ってラスタライズし、
+// 透視補正した重心座標を持つフラグメントを 2x2 ブロック単位で渡す
+pub fn rasterize_clipped<F>(clip: [[f32; 4]; 3], width: usize, height: usize, emit: F)
+where
+    F: FnMut(&FragmentQuad),
+{
+    rasterize_clipped_sampled(clip, width, height, &CENTER_SAMPLE, emit);
+}
+
+// MSAA 用。FragmentQuad::sample_mask に samples の各位置の被覆が入る
+pub fn rasterize_clipped_sampled<F>(
+    clip: [[f32; 4]; 3],
+    width: usize,
+    height: usize,
+    samples: &[(f32, f32)],
+    mut emit: F,
+) where
+    F: FnMut(&FragmentQuad),
+{
+    let mut polygon = Vec::with_capacity(8);
+    let mut scratch = Vec::with_capacity(8);
+    clip_triangle(clip, width, height, &mut polygon, &mut scratch);
+    if polygon.len() < 3 {
+        return;
+    }
+
+    let screen: Vec<ScreenVertex> = polygon
+        .iter()
+        .map(|v| ScreenVertex::from_clip(v.position, width as f32, height as f32))
+        .collect();
+
+    for i in 1..polygon.len() - 1 {
+        let indices = [0, i, i + 1];
+        let s = indices.map(|k| screen[k]);
+        let original = indices.map(|k| polygon[k].barycentric);
+
+        rasterize_triangle_quads_sampled(
+            s[0].position,
+            s[1].position,
+            s[2].position,
+            width,
+            height,
+            samples,
+            |quad| {
+                let mut lanes = [Fragment {
+                    x: 0,
+                    y: 0,
+                    depth: 0.0,
+                    w: 0.0,
+                    barycentric: [0.0; 3],
+                }; 4];
+
+                for (lane, fragment) in lanes.iter_mut().enumerate() {
+                    let b = quad.barycentric[lane];
+
+                    // 1/w で重み付けして透視補正する。
+                    // 三角形の外に外挿したレーンでは 1/w が 0 以下になりうるので下限を設ける
+                    let weights = [b[0] * s[0].inv_w, b[1] * s[1].inv_w, b[2] * s[2].inv_w];
+                    let inv_w = (weights[0] + weights[1] + weights[2]).max(f32::EPSILON);
+                    let w = 1.0 / inv_w;
+
+                    let mut barycentric = [0.0; 3];
+                    for (k, weight) in weights.iter().enumerate() {
+                        let corrected = weight * w;
+                        for (j, out) in barycentric.iter_mut().enumerate() {
+                            *out += corrected * original[k][j];
+                        }
+                    }
+
+                    *fragment = Fragment {
+                        x: quad.x + QUAD_OFFSETS[lane].0,
+                        y: quad.y + QUAD_OFFSETS[lane].1,
+                        depth: b[0] * s[0].depth + b[1] * s[1].depth + b[2] * s[2].depth,
+                        w,
+                        barycentric,
+                    };
+                }
+
+                emit(&FragmentQuad {
+                    fragments: lanes,
+                    coverage: quad.coverage,
+                    sample_mask: quad.sample_mask,
+                });
+            },
+        );
+    }
+}
+
+// クリップ座標の線分をニア平面で切り取る。全体がニア平面の手前なら None
+pub fn clip_line_near(a: [f32; 4], b: [f32; 4]) -> Option<([f32; 4], [f32; 4])> {
+    let da = a[2] + a[3];
+    let db = b[2] + b[3];
+    if da < 0.0 && db < 0.0 {
+        return None;
+    }
+
+    let lerp = |t: f32| {
+        let mut out = [0.0; 4];
+        for (i, value) in out.iter_mut().enumerate() {
+            *value = a[i] + (b[i] - a[i]) * t;
+        }
+        out
+    };
+    if da < 0.0 {
+        Some((lerp(da / (da - db)), b))
+    } else if db < 0.0 {
+        Some((a, lerp(da / (da - db))))
+    } else {
+        Some((a, b))
+    }
+}
+
+// 線分のうち画面 [0, width] x [0, height] に入る範囲 (t0, t1) を求める (Liang-Barsky)
+pub fn clip_segment_to_viewport(p0: Vec2, p1: Vec2, width: f32, height: f32) -> Option<(f32, f32)> {
+    let (dx, dy) = (p1.x - p0.x, p1.y - p0.y);
+    let mut t0: f32 = 0.0;
+    let mut t1: f32 = 1.0;
+
+    for (p, q) in [
+        (-dx, p0.x),
+        (dx, width - p0.x),
+        (-dy, p0.y),
+        (dy, height - p0.y),
+    ] {
+        if p == 0.0 {
+            if q < 0.0 {
+                return None;
+            }
+            continue;
+        }
+        let t = q / p;
+        if p < 0.0 {
+            t0 = t0.max(t);
+        } else {
+            t1 = t1.min(t);
+        }
+    }
+
+    (t0 <= t1).then_some((t0, t1))
+}
+
+// 線分のラスタライズで生成される 1 ピクセル分の情報
+#[derive(Clone, Copy, Debug)]
+pub struct LineFragment {
+    pub x: i32,
+    pub y: i32,
+    // [0, 1] の NDC 深度
+    pub depth: f32,
+    // クリップ座標の w (視点からの距離)
+    pub w: f32,
+    // ピクセルを線が覆う割合。アンチエイリアスしない線では常に 1
+    pub coverage: f32,
+}
+
+// 線分上の位置 t での (画面 x, 画面 y, 深度, 1/w)。NDC 深度と 1/w は画面上で線形
+fn line_point(a: &ScreenVertex, b: &ScreenVertex, t: f32) -> (f32, f32, f32, f32) {
+    (
+        a.position.x + (b.position.x - a.position.x) * t,
+        a.position.y + (b.position.y - a.position.y) * t,
+        a.depth + (b.depth - a.depth) * t,
+        a.inv_w + (b.inv_w - a.inv_w) * t,
+    )
+}
+
+// 線分を Bresenham で描き、各ピクセルの深度と w を添えて渡す
+pub fn rasterize_line<F>(a: ScreenVertex, b: ScreenVertex, width: usize, height: usize, mut emit: F)
+where
+    F: FnMut(&LineFragment),
+{
+    let Some((t0, t1)) =
+        clip_segment_to_viewport(a.position, b.position, width as f32, height as f32)
+    else {
+        return;
+    };
+    let (x0, y0, z0, q0) = line_point(&a, &b, t0);
+    let (x1, y1, z1, q1) = line_point(&a, &b, t1);
+
+    let mut x0 = (x0.floor() as i32).clamp(0, width as i32 - 1);
+    let mut y0 = (y0.floor() as i32).clamp(0, height as i32 - 1);
+    let mut x1 = (x1.floor() as i32).clamp(0, width as i32 - 1);
+    let mut y1 = (y1.floor() as i32).clamp(0, height as i32 - 1);
+    let (mut start, mut end) = ((z0, q0), (z1, q1));
+
+    let steep = (x0 - x1).abs() < (y0 - y1).abs();
+    if steep {
+        std::mem::swap(&mut x0, &mut y0);
+        std::mem::swap(&mut x1, &mut y1);
+    }
+    if x0 > x1 {
+        std::mem::swap(&mut x0, &mut x1);
+        std::mem::swap(&mut y0, &mut y1);
+        std::mem::swap(&mut start, &mut end);
+    }
+
+    let dx = x1 - x0;
+    let dy = y1 - y0;
+    let derror2 = dy.abs() * 2;
+    let mut error2 = 0;
+    let mut y = y0;
+
+    for x in x0..=x1 {
+        let t = if dx > 0 {
+            (x - x0) as f32 / dx as f32
+        } else {
+            0.0
+        };
+        let inv_w = start.1 + (end.1 - start.1) * t;
+        let (px, py) = if steep { (y, x) } else { (x, y) };
+        emit(&LineFragment {
+            x: px,
+            y: py,
+            depth: start.0 + (end.0 - start.0) * t,
+            w: 1.0 / inv_w.max(f32::EPSILON),
+            coverage: 1.0,
+        });
+
+        error2 += derror2;
+        if error2 > dx {
+            y += if y1 > y0 { 1 } else { -1 };
+            error2 -= dx * 2;
+        }
+    }
+}
+
+// Xiaolin Wu のアンチエイリアス線。各ピクセルに深度・w と被覆率を添えて渡す。
+// 画面座標はピクセル i が [i, i + 1) を覆う (中心は i + 0.5)
+pub fn rasterize_line_aa<F>(
+    a: ScreenVertex,
+    b: ScreenVertex,
+    width: usize,
+    height: usize,
+    mut emit: F,
+) where
+    F: FnMut(&LineFragment),
+{
+    // 端のピクセルも欠けないよう 1 ピクセル外側まで残す
+    let Some((t0, t1)) = clip_segment_to_viewport(
+        Vec2::new(a.position.x + 1.0, a.position.y + 1.0),
+        Vec2::new(b.position.x + 1.0, b.position.y + 1.0),
+        width as f32 + 2.0,
+        height as f32 + 2.0,
+    ) else {
+        return;
+    };
+    let (mut x0, mut y0, z0, q0) = line_point(&a, &b, t0);
+    let (mut x1, mut y1, z1, q1) = line_point(&a, &b, t1);
+    (x0, y0, x1, y1) = (x0 - 0.5, y0 - 0.5, x1 - 0.5, y1 - 0.5);
+    let (mut start, mut end) = ((z0, q0), (z1, q1));
+
+    let steep = (y1 - y0).abs() > (x1 - x0).abs();
+    if steep {
+        std::mem::swap(&mut x0, &mut y0);
+        std::mem::swap(&mut x1, &mut y1);
+    }
+    if x0 > x1 {
+        std::mem::swap(&mut x0, &mut x1);
+        std::mem::swap(&mut y0, &mut y1);
+        std::mem::swap(&mut start, &mut end);
+    }
+
+    let dx = x1 - x0;
+    let gradient = if dx > 0.0 { (y1 - y0) / dx } else { 1.0 };
+    // 主軸上の位置での (深度, w)
+    let depth_at = |x: f32| {
+        let t = if dx > 0.0 {
+            ((x - x0) / dx).clamp(0.0, 1.0)
+        } else {
+            0.0
+        };
+        let inv_w = start.1 + (end.1 - start.1) * t;
+        (
+            start.0 + (end.0 - start.0) * t,
+            1.0 / inv_w.max(f32::EPSILON),
+        )
+    };
+
+    let mut plot = |major: i32, minor: i32, (depth, w): (f32, f32), coverage: f32| {
+        let (x, y) = if steep {
+            (minor, major)
+        } else {
+            (major, minor)
+        };
+        if coverage > 0.0 && x >= 0 && x < width as i32 && y >= 0 && y < height as i32 {
+            emit(&LineFragment {
+                x,
+                y,
+                depth,
+                w,
+                coverage,
+            });
+        }
+    };
+
+    // 端点は線が覆う長さ (xgap) で被覆率を減らす
+    let mut endpoint = |x: f32, y: f32, xgap: f32| {
+        let x_end = x.round();
+        let y_end = y + gradient * (x_end - x);
+        let fraction = y_end - y_end.floor();
+        let depth = depth_at(x_end);
+        plot(
+            x_end as i32,
+            y_end.floor() as i32,
+            depth,
+            (1.0 - fraction) * xgap,
+        );
+        plot(
+            x_end as i32,
+            y_end.floor() as i32 + 1,
+            depth,
+            fraction * xgap,
+        );
+        (x_end as i32, y_end)
+    };
+
+    let (first, y_start) = endpoint(x0, y0, 1.0 - (x0 + 0.5 - (x0 + 0.5).floor()));
+    let (last, _) = endpoint(x1, y1, x1 + 0.5 - (x1 + 0.5).floor());
+
+    let mut intery = y_start + gradient;
+    for x in first + 1..last {
+        let fraction = intery - intery.floor();
+        let depth = depth_at(x as f32);
+        plot(x, intery.floor() as i32, depth, 1.0 - fraction);
+        plot(x, intery.floor() as i32 + 1, depth, fraction);
+        intery += gradient;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn coverage_counts(triangles: &[[Vec2; 3]], width: usize, height: usize) -> Vec<u32> {
+        let mut counts = vec![0; width * height];
+        for &[p0, p1, p2] in triangles {
+            rasterize_triangle_quads_sampled(p0, p1, p2, width, height, &CENTER_SAMPLE, |quad| {
+                for (lane, &(dx, dy)) in QUAD_OFFSETS.iter().enumerate() {
+                    if quad.coverage[lane] {
+                        counts[(quad.y + dy) as usize * width + (quad.x + dx) as usize] += 1;
+                    }
+                }
+            });
+        }
+        counts
+    }
+
+    // N x M の四角形を 2 枚の三角形に分けて並べた格子。
+    // 内側の格子点は小数のサブピクセル位置にずらし、対角線の向きも交互に変える
+    fn grid_triangles(xs: &[f32], ys: &[f32]) -> Vec<[Vec2; 3]> {
+        let point = |i: usize, j: usize| {
+            let inner = i > 0 && i < xs.len() - 1 && j > 0 && j < ys.len() - 1;
+            let jitter = if inner {
+                Vec2::new(
+                    0.37 * (i as f32 - j as f32).sin(),
+                    0.29 * (i * j) as f32 % 0.61,
+                )
+            } else {
+                Vec2::new(0.0, 0.0)
+            };
+            Vec2::new(xs[i] + jitter.x, ys[j] + jitter.y)
+        };
+
+        let mut triangles = Vec::new();
+        for j in 0..ys.len() - 1 {
+            for i in 0..xs.len() - 1 {
+                let (a, b, c, d) = (
+                    point(i, j),
+                    point(i + 1, j),
+                    point(i + 1, j + 1),
+                    point(i, j + 1),
+                );
+                if (i + j) % 2 == 0 {
+                    triangles.push([a, b, c]);
+                    triangles.push([a, c, d]);
+                } else {
+                    // 逆向きの巻き方向も混ぜる
+                    triangles.push([a, d, b]);
+                    triangles.push([b, d, c]);
+                }
+            }
+        }
+        triangles
+    }
+
+    #[test]
+    fn grid_of_quads_covers_every_pixel_exactly_once() {
+        let (width, height) = (40, 30);
+        let xs: Vec<f32> = (0..=7).map(|i| 1.3 + i as f32 * 4.71).collect();
+        let ys: Vec<f32> = (0..=5).map(|j| 0.6 + j as f32 * 5.13).collect();
+        let counts = coverage_counts(&grid_triangles(&xs, &ys), width, height);
+
+        for y in 0..height {
+            for x in 0..width {
+                let (cx, cy) = (x as f32 + 0.5, y as f32 + 0.5);
+                let inside =
+                    cx > xs[0] && cx < xs[xs.len() - 1] && cy > ys[0] && cy < ys[ys.len() - 1];
+                assert_eq!(counts[y * width + x], inside as u32, "pixel ({x}, {y})");
+            }
+        }
+    }
+
+    #[test]
+    fn grid_on_pixel_centers_shares_edges_without_overlap() {
+        // 格子点をすべてピクセル中心に置き、辺の上のサンプルを top-left ルールだけで振り分ける
+        let (width, height) = (24, 24);
+        let xs: Vec<f32> = (0..=5).map(|i| 1.5 + i as f32 * 4.0).collect();
+        let ys: Vec<f32> = (0..=4).map(|j| 2.5 + j as f32 * 5.0).collect();
+        let mut triangles = Vec::new();
+        for j in 0..ys.len() - 1 {
+            for i in 0..xs.len() - 1 {
+                let a = Vec2::new(xs[i], ys[j]);
+                let b = Vec2::new(xs[i + 1], ys[j]);
+                let c = Vec2::new(xs[i + 1], ys[j + 1]);
+                let d = Vec2::new(xs[i], ys[j + 1]);
+                triangles.push([a, b, c]);
+                triangles.push([a, c, d]);
+            }
+        }
+        let counts = coverage_counts(&triangles, width, height);
+
+        for y in 0..height {
+            for x in 0..width {
+                let (cx, cy) = (x as f32 + 0.5, y as f32 + 0.5);
+                // 左辺と上辺の上は含み、右辺と下辺の上は含まない
+                let inside =
+                    cx >= xs[0] && cx < xs[xs.len() - 1] && cy >= ys[0] && cy < ys[ys.len() - 1];
+                assert_eq!(counts[y * width + x], inside as u32, "pixel ({x}, {y})");
+            }
+        }
+    }
+
+    #[test]
+    fn top_left_rule_drops_right_and_bottom_edges() {
+        // 3 辺ともピクセル中心をちょうど通る直角三角形
+        let (width, height) = (8, 8);
+        let p0 = Vec2::new(1.5, 1.5);
+        let p1 = Vec2::new(5.5, 1.5);
+        let p2 = Vec2::new(1.5, 5.5);
+        let counts = coverage_counts(&[[p0, p1, p2]], width, height);
+        // 上辺 (y = 1.5) と左辺 (x = 1.5) の上は含む
+        assert_eq!(counts[width + 1], 1);
+        assert_eq!(counts[width + 4], 1);
+        assert_eq!(counts[4 * width + 1], 1);
+        // 斜辺は右下側の辺なので、その上のピクセル中心 (x + y = 7) は含まない
+        assert_eq!(counts[width + 5], 0);
+        assert_eq!(counts[3 * width + 3], 0);
+        assert_eq!(counts[5 * width + 1], 0);
+
+        // 右下の三角形では縦と横の辺が右辺と下辺になって含まれず、斜辺は左上の辺として含まれる
+        let q0 = Vec2::new(5.5, 1.5);
+        let q1 = Vec2::new(5.5, 5.5);
+        let q2 = Vec2::new(1.5, 5.5);
+        let counts = coverage_counts(&[[q0, q1, q2]], width, height);
+        assert_eq!(counts[2 * width + 5], 0);
+        assert_eq!(counts[5 * width + 3], 0);
+        assert_eq!(counts[3 * width + 3], 1);
+        assert_eq!(counts[4 * width + 4], 1);
+    }
+
+    #[test]
+    fn huge_triangle_is_clipped_to_guard_band_and_covers_screen() {
+        // 頂点が画面座標で ±16384 を大きく超える三角形
+        let (width, height) = (32, 24);
+        let clip = [
+            [-2000.0, -2000.0, 0.0, 1.0],
+            [6000.0, -2000.0, 0.0, 1.0],
+            [-2000.0, 6000.0, 0.0, 1.0],
+        ];
+        let mut counts = vec![0; width * height];
+        rasterize_clipped(clip, width, height, |quad| {
+            for (fragment, &covered) in quad.fragments.iter().zip(&quad.coverage) {
+                if !covered {
+                    continue;
+                }
+                counts[fragment.y as usize * width + fragment.x as usize] += 1;
+
+                // 切り取った後も重心座標は元の三角形に対する値になっている
+                let ndc_x = fragment.interpolate(clip.map(|c| c[0]));
+                let ndc_y = fragment.interpolate(clip.map(|c| c[1]));
+                let expected_x = (fragment.x as f32 + 0.5) / width as f32 * 2.0 - 1.0;
+                let expected_y = 1.0 - (fragment.y as f32 + 0.5) / height as f32 * 2.0;
+                assert!((ndc_x - expected_x).abs() < 1e-3, "{ndc_x} {expected_x}");
+                assert!((ndc_y - expected_y).abs() < 1e-3, "{ndc_y} {expected_y}");
+                assert!((fragment.depth - 0.5).abs() < 1e-5);
+            }
+        });
+        assert!(counts.iter().all(|&c| c == 1), "{counts:?}");
+    }
+}
@@ -0,0 +1,464 @@
+use crate::terminal_gl::Canvas;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+// レンダリング結果の録画 (asciicast v2 / アニメーション GIF)
+pub struct Recorder {
+    sink: Sink,
+    start: Instant,
+    frame_count: usize,
+}
+
+enum Sink {
+    Asciicast(AsciicastWriter<BufWriter<File>>),
+    Gif(GifWriter<BufWriter<File>>),
+}
+
+impl Recorder {
+    pub fn asciicast<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let file = BufWriter::new(File::create(path)?);
+        Ok(Self::with_sink(Sink::Asciicast(AsciicastWriter::new(file))))
+    }
+
+    pub fn gif<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let file = BufWriter::new(File::create(path)?);
+        Ok(Self::with_sink(Sink::Gif(GifWriter::new(file))))
+    }
+
+    // 拡張子が .gif なら GIF、それ以外は asciicast
+    pub fn create<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let is_gif = path
+            .as_ref()
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("gif"));
+
+        if is_gif {
+            Self::gif(path)
+        } else {
+            Self::asciicast(path)
+        }
+    }
+
+    fn with_sink(sink: Sink) -> Self {
+        Self {
+            sink,
+            start: Instant::now(),
+            frame_count: 0,
+        }
+    }
+
+    // present 直後のキャンバスを 1 フレームとして記録する
+    pub fn capture(&mut self, canvas: &Canvas) -> io::Result<()> {
+        let time = self.start.elapsed().as_secs_f64();
+
+        match &mut self.sink {
+            Sink::Asciicast(writer) => writer.write_frame(canvas, time)?,
+            Sink::Gif(writer) => writer.write_frame(canvas, time)?,
+        }
+
+        self.frame_count += 1;
+        Ok(())
+    }
+
+    pub fn frame_count(&self) -> usize {
+        self.frame_count
+    }
+
+    pub fn finish(self) -> io::Result<()> {
+        let time = self.start.elapsed().as_secs_f64();
+
+        match self.sink {
+            Sink::Asciicast(writer) => writer.finish(),
+            Sink::Gif(writer) => writer.finish(time),
+        }
+    }
+}
+
+pub struct AsciicastWriter<W: Write> {
+    out: W,
+    header_written: bool,
+}
+
+impl<W: Write> AsciicastWriter<W> {
+    pub fn new(out: W) -> Self {
+        Self {
+            out,
+            header_written: false,
+        }
+    }
+
+    pub fn write_frame(&mut self, canvas: &Canvas, time: f64) -> io::Result<()> {
+        if !self.header_written {
+            let timestamp = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or(0);
+            writeln!(
+                self.out,
+                "{{\"version\": 2, \"width\": {}, \"height\": {}, \"timestamp\": {}, \"env\": {{\"TERM\": \"xterm-256color\"}}}}",
+                canvas.width,
+                canvas.rows(),
+                timestamp
+            )?;
+            self.header_written = true;
+        }
+
+        writeln!(
+            self.out,
+            "[{:.6}, \"o\", \"{}\"]",
+            time,
+            json_escape(canvas.last_frame())
+        )?;
+        self.out.flush()
+    }
+
+    pub fn finish(mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+}
+
+fn json_escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len() + text.len() / 4);
+    for c in text.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c if (c as u32) < 0x20 || c as u32 == 0x7f => {
+                escaped.push_str(&format!("\\u{:04x}", c as u32));
+            }
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+// 3-3-2 ビットの固定 256 色パレット
+fn palette_index(r: u8, g: u8, b: u8) -> u8 {
+    let r = (r as u32 * 7 + 127) / 255;
+    let g = (g as u32 * 7 + 127) / 255;
+    let b = (b as u32 * 3 + 127) / 255;
+    (r << 5 | g << 2 | b) as u8
+}
+
+fn palette_color(index: u8) -> [u8; 3] {
+    let r = (index >> 5) & 0x7;
+    let g = (index >> 2) & 0x7;
+    let b = index & 0x3;
+    [
+        (r as u32 * 255 / 7) as u8,
+        (g as u32 * 255 / 7) as u8,
+        (b as u32 * 255 / 3) as u8,
+    ]
+}
+
+pub struct GifWriter<W: Write> {
+    out: W,
+    width: u16,
+    height: u16,
+    header_written: bool,
+    // 表示時間は次のフレームが来るまで分からないので 1 フレーム遅れで書き出す
+    pending: Option<(Vec<u8>, f64)>,
+}
+
+impl<W: Write> GifWriter<W> {
+    pub fn new(out: W) -> Self {
+        Self {
+            out,
+            width: 0,
+            height: 0,
+            header_written: false,
+            pending: None,
+        }
+    }
+
+    pub fn write_frame(&mut self, canvas: &Canvas, time: f64) -> io::Result<()> {
+        if !self.header_written {
+            self.width = canvas.width.min(u16::MAX as usize) as u16;
+            self.height = canvas.height.min(u16::MAX as usize) as u16;
+            self.write_header()?;
+            self.header_written = true;
+        }
+
+        let (width, height) = (self.width as usize, self.height as usize);
+        let mut indices = Vec::with_capacity(width * height);
+        for y in 0..height {
+            for x in 0..width {
+                let idx = y * canvas.width + x;
+                indices.push(palette_index(canvas.r[idx], canvas.g[idx], canvas.b[idx]));
+            }
+        }
+
+        if let Some((previous, previous_time)) = self.pending.take() {
+            self.write_image(&previous, time - previous_time)?;
+        }
+        self.pending = Some((indices, time));
+        Ok(())
+    }
+
+    pub fn finish(mut self, time: f64) -> io::Result<()> {
+        if let Some((previous, previous_time)) = self.pending.take() {
+            self.write_image(&previous, time - previous_time)?;
+        }
+        if self.header_written {
+            self.out.write_all(&[0x3b])?;
+        }
+        self.out.flush()
+    }
+
+    fn write_header(&mut self) -> io::Result<()> {
+        self.out.write_all(b"GIF89a")?;
+        self.out.write_all(&self.width.to_le_bytes())?;
+        self.out.write_all(&self.height.to_le_bytes())?;
+        // グローバルカラーテーブルあり、256 色
+        self.out.write_all(&[0xf7, 0, 0])?;
+        for index in 0..=255u8 {
+            self.out.write_all(&palette_color(index))?;
+        }
+
+        // NETSCAPE2.0 拡張: 無限ループ
+        self.out.write_all(&[0x21, 0xff, 0x0b])?;
+        self.out.write_all(b"NETSCAPE2.0")?;
+        self.out.write_all(&[0x03, 0x01, 0x00, 0x00, 0x00])
+    }
+
+    fn write_image(&mut self, indices: &[u8], duration: f64) -> io::Result<()> {
+        let delay = (duration * 100.0).round().clamp(1.0, u16::MAX as f64) as u16;
+
+        // Graphic Control Extension
+        self.out.write_all(&[0x21, 0xf9, 0x04, 0x04])?;
+        self.out.write_all(&delay.to_le_bytes())?;
+        self.out.write_all(&[0x00, 0x00])?;
+
+        // Image Descriptor
+        self.out.write_all(&[0x2c, 0, 0, 0, 0])?;
+        self.out.write_all(&self.width.to_le_bytes())?;
+        self.out.write_all(&self.height.to_le_bytes())?;
+        self.out.write_all(&[0x00])?;
+
+        self.out.write_all(&[8])?;
+        for block in lzw_encode(indices, 8).chunks(255) {
+            self.out.write_all(&[block.len() as u8])?;
+            self.out.write_all(block)?;
+        }
+        self.out.write_all(&[0x00])
+    }
+}
+
+struct BitWriter {
+    bytes: Vec<u8>,
+    buffer: u32,
+    bits: u32,
+}
+
+impl BitWriter {
+    fn write(&mut self, code: u16, width: u32) {
+        self.buffer |= (code as u32) << self.bits;
+        self.bits += width;
+        while self.bits >= 8 {
+            self.bytes.push(self.buffer as u8);
+            self.buffer >>= 8;
+            self.bits -= 8;
+        }
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.bits > 0 {
+            self.bytes.push(self.buffer as u8);
+        }
+        self.bytes
+    }
+}
+
+// GIF 用の可変長 LZW 圧縮 (最大 12 ビット)
+pub fn lzw_encode(data: &[u8], min_code_size: u32) -> Vec<u8> {
+    const MAX_CODE: u16 = 4096;
+
+    let clear_code: u16 = 1 << min_code_size;
+    let end_code = clear_code + 1;
+
+    let mut writer = BitWriter {
+        bytes: Vec::with_capacity(data.len() / 2),
+        buffer: 0,
+        bits: 0,
+    };
+    let mut dictionary: HashMap<(u16, u8), u16> = HashMap::new();
+    let mut next_code = end_code + 1;
+    let mut code_width = min_code_size + 1;
+
+    writer.write(clear_code, code_width);
+
+    let Some((&first, rest)) = data.split_first() else {
+        writer.write(end_code, code_width);
+        return writer.finish();
+    };
+
+    let mut prefix = first as u16;
+    for &k in rest {
+        if let Some(&code) = dictionary.get(&(prefix, k)) {
+            prefix = code;
+            continue;
+        }
+
+        writer.write(prefix, code_width);
+
+        if next_code < MAX_CODE {
+            dictionary.insert((prefix, k), next_code);
+            if next_code == 1 << code_width {
+                code_width += 1;
+            }
+            next_code += 1;
+        } else {
+            writer.write(clear_code, code_width);
+            dictionary.clear();
+            next_code = end_code + 1;
+            code_width = min_code_size + 1;
+        }
+
+        prefix = k as u16;
+    }
+
+    writer.write(prefix, code_width);
+    writer.write(end_code, code_width);
+    writer.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // GIF の LZW を読み戻す (コード幅の切り替えとクリアコードを含む)
+    fn lzw_decode(bytes: &[u8], min_code_size: u32) -> Vec<u8> {
+        let clear_code = 1u16 << min_code_size;
+        let end_code = clear_code + 1;
+
+        let mut dictionary: Vec<Vec<u8>> = Vec::new();
+        let reset = |dictionary: &mut Vec<Vec<u8>>| {
+            dictionary.clear();
+            dictionary.extend((0..clear_code).map(|i| vec![i as u8]));
+            dictionary.push(Vec::new());
+            dictionary.push(Vec::new());
+        };
+        reset(&mut dictionary);
+
+        let mut output = Vec::new();
+        let mut code_width = min_code_size + 1;
+        let mut previous: Option<u16> = None;
+        let (mut buffer, mut bits) = (0u32, 0u32);
+        let mut input = bytes.iter();
+
+        loop {
+            while bits < code_width {
+                buffer |= (*input.next().expect("missing end code") as u32) << bits;
+                bits += 8;
+            }
+            let code = (buffer & ((1 << code_width) - 1)) as u16;
+            buffer >>= code_width;
+            bits -= code_width;
+
+            if code == clear_code {
+                reset(&mut dictionary);
+                code_width = min_code_size + 1;
+                previous = None;
+                continue;
+            }
+            if code == end_code {
+                return output;
+            }
+
+            let entry = match previous {
+                None => dictionary[code as usize].clone(),
+                Some(previous) => {
+                    let previous = dictionary[previous as usize].clone();
+                    let entry = match dictionary.get(code as usize) {
+                        Some(entry) => entry.clone(),
+                        None => [previous.clone(), vec![previous[0]]].concat(),
+                    };
+                    if dictionary.len() < 4096 {
+                        dictionary.push([previous, vec![entry[0]]].concat());
+                    }
+                    entry
+                }
+            };
+            if dictionary.len() == 1 << code_width && code_width < 12 {
+                code_width += 1;
+            }
+            output.extend_from_slice(&entry);
+            previous = Some(code);
+        }
+    }
+
+    #[test]
+    fn lzw_round_trips_through_code_width_changes_and_clears() {
+        // 擬似乱数で辞書を 4096 まで埋めてクリアコードを出させる
+        let mut seed = 12345u32;
+        let data: Vec<u8> = (0..40_000)
+            .map(|_| {
+                seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+                (seed >> 16) as u8
+            })
+            .collect();
+        assert_eq!(lzw_decode(&lzw_encode(&data, 8), 8), data);
+
+        // 繰り返しの多い入力と 2 ビットの最小コード長
+        let data: Vec<u8> = (0..5000).map(|i| (i / 7 % 4) as u8).collect();
+        assert_eq!(lzw_decode(&lzw_encode(&data, 2), 2), data);
+
+        assert!(lzw_decode(&lzw_encode(&[], 8), 8).is_empty());
+    }
+
+    #[test]
+    fn gif_has_header_screen_size_and_trailer() {
+        let mut canvas = Canvas::new(6, 4);
+        canvas.clear_with(255, 0, 0);
+
+        let mut bytes = Vec::new();
+        let mut writer = GifWriter::new(&mut bytes);
+        writer.write_frame(&canvas, 0.0).unwrap();
+        writer.write_frame(&canvas, 0.1).unwrap();
+        writer.finish(0.2).unwrap();
+
+        assert_eq!(&bytes[..6], b"GIF89a");
+        assert_eq!(u16::from_le_bytes([bytes[6], bytes[7]]), 6);
+        assert_eq!(u16::from_le_bytes([bytes[8], bytes[9]]), 4);
+        assert_eq!(bytes.last(), Some(&0x3b));
+        // 2 フレームとも画像記述子がある
+        let descriptors = bytes
+            .windows(5)
+            .filter(|w| w == &[0x2c, 0, 0, 0, 0])
+            .count();
+        assert_eq!(descriptors, 2);
+    }
+
+    #[test]
+    fn asciicast_writes_header_then_output_events() {
+        let canvas = Canvas::new(8, 6);
+
+        let mut bytes = Vec::new();
+        let mut writer = AsciicastWriter::new(&mut bytes);
+        writer.write_frame(&canvas, 0.0).unwrap();
+        writer.write_frame(&canvas, 0.5).unwrap();
+        writer.finish().unwrap();
+
+        let text = String::from_utf8(bytes).unwrap();
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert!(lines[0].starts_with("{\"version\": 2, \"width\": 8, \"height\": 3,"));
+        assert!(lines[0].ends_with('}'));
+        assert_eq!(lines[1], "[0.000000, \"o\", \"\"]");
+        assert_eq!(lines[2], "[0.500000, \"o\", \"\"]");
+    }
+
+    #[test]
+    fn json_escape_handles_quotes_and_control_characters() {
+        assert_eq!(
+            json_escape("\x1b[0m\"a\\b\"\n"),
+            "\\u001b[0m\\\"a\\\\b\\\"\\n"
+        );
+    }
+}
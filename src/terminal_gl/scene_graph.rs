@@ -0,0 +1,175 @@
+use crate::matrix::{Mat4, Transform};
+use crate::mesh::Mesh;
+use std::ops::{Index, IndexMut};
+
+// シーングラフのノードを指すハンドル。ノードを足した SceneGraph の中でだけ有効
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct NodeId(usize);
+
+pub struct Node {
+    pub name: String,
+    // 親ノードに対する変換 (ルートならワールドに対する変換)
+    pub transform: Transform,
+    // メッシュ自身の transform はノードの中での配置として、さらに内側に掛ける
+    pub mesh: Option<Mesh>,
+    children: Vec<NodeId>,
+}
+
+impl Node {
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            transform: Transform::new(),
+            mesh: None,
+            children: Vec::new(),
+        }
+    }
+
+    pub fn with_mesh(mut self, mesh: Mesh) -> Self {
+        self.mesh = Some(mesh);
+        self
+    }
+}
+
+// ノードを配列に持ち、親子関係をハンドルでつなぐ
+pub struct SceneGraph {
+    nodes: Vec<Node>,
+    roots: Vec<NodeId>,
+}
+
+impl SceneGraph {
+    pub fn new() -> Self {
+        Self {
+            nodes: Vec::new(),
+            roots: Vec::new(),
+        }
+    }
+
+    // ルートにノードを足す
+    pub fn add(&mut self, node: Node) -> NodeId {
+        let id = self.push(node);
+        self.roots.push(id);
+        id
+    }
+
+    // parent の子としてノードを足す
+    pub fn add_child(&mut self, parent: NodeId, node: Node) -> NodeId {
+        let id = self.push(node);
+        self.nodes[parent.0].children.push(id);
+        id
+    }
+
+    fn push(&mut self, mut node: Node) -> NodeId {
+        let id = NodeId(self.nodes.len());
+        node.children.clear();
+        self.nodes.push(node);
+        id
+    }
+
+    pub fn roots(&self) -> &[NodeId] {
+        &self.roots
+    }
+
+    // 名前が一致する最初のノード (足した順)
+    pub fn find(&self, name: &str) -> Option<NodeId> {
+        self.nodes
+            .iter()
+            .position(|node| node.name == name)
+            .map(NodeId)
+    }
+
+    // 足した順にすべてのノードを返す
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = (NodeId, &mut Node)> {
+        self.nodes
+            .iter_mut()
+            .enumerate()
+            .map(|(i, node)| (NodeId(i), node))
+    }
+
+    // メッシュを持つノードごとのメッシュとモデル行列。
+    // 親のワールド行列を子へ渡しながら木をたどるので、各ノードの行列は 1 回だけ計算する
+    pub fn mesh_instances(&self) -> Vec<(&Mesh, Mat4)> {
+        let mut instances = Vec::new();
+        let mut stack: Vec<(NodeId, Mat4)> = self
+            .roots
+            .iter()
+            .rev()
+            .map(|&id| (id, Mat4::identity()))
+            .collect();
+        while let Some((id, parent_world)) = stack.pop() {
+            let node = &self.nodes[id.0];
+            let world = parent_world.multiply(&node.transform.to_matrix());
+            if let Some(mesh) = &node.mesh {
+                instances.push((mesh, world.multiply(&mesh.transform.to_matrix())));
+            }
+            stack.extend(node.children.iter().rev().map(|&child| (child, world)));
+        }
+        instances
+    }
+}
+
+impl Default for SceneGraph {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Index<NodeId> for SceneGraph {
+    type Output = Node;
+
+    fn index(&self, id: NodeId) -> &Node {
+        &self.nodes[id.0]
+    }
+}
+
+impl IndexMut<NodeId> for SceneGraph {
+    fn index_mut(&mut self, id: NodeId) -> &mut Node {
+        &mut self.nodes[id.0]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geometry::Vec3;
+    use std::f32::consts::FRAC_PI_2;
+
+    #[test]
+    fn mesh_instances_compose_parent_transforms() {
+        let mut graph = SceneGraph::new();
+        let mut parent = Node::new("parent").with_mesh(Mesh::create_cube(1.0));
+        parent.transform.position = Vec3::new(1.0, 0.0, 0.0);
+        parent.transform.set_rotation_y(FRAC_PI_2);
+        let parent = graph.add(parent);
+
+        // メッシュのないノードの変換も子に伝わる
+        let mut pivot = Node::new("pivot");
+        pivot.transform.position = Vec3::new(0.0, 0.0, 2.0);
+        let pivot = graph.add_child(parent, pivot);
+        let mut mesh = Mesh::create_cube(1.0);
+        mesh.transform.position = Vec3::new(0.0, 1.0, 0.0);
+        graph.add_child(pivot, Node::new("child").with_mesh(mesh));
+        graph.add(Node::new("other").with_mesh(Mesh::create_cube(1.0)));
+
+        // 親の次にその子孫、そのあとで次のルート
+        let origins: Vec<Vec3> = graph
+            .mesh_instances()
+            .iter()
+            .map(|(_, model)| model.transform_point(Vec3::new(0.0, 0.0, 0.0)))
+            .collect();
+        let expected = [
+            Vec3::new(1.0, 0.0, 0.0),
+            // pivot の (0, 0, 2) は親の y 軸回転で (2, 0, 0) になる
+            Vec3::new(3.0, 1.0, 0.0),
+            Vec3::new(0.0, 0.0, 0.0),
+        ];
+        assert_eq!(origins.len(), expected.len());
+        for (origin, expected) in origins.iter().zip(&expected) {
+            assert!(origin.approx_eq(expected, 1e-5), "{origin:?} {expected:?}");
+        }
+        assert_eq!(
+            graph.find("child").map(|id| graph[id].name.as_str()),
+            Some("child")
+        );
+    }
+}
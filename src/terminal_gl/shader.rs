@@ -0,0 +1,384 @@
+use crate::geometry::{Color, Vec2, Vec3};
+use crate::light::Light;
+use crate::material::Material;
+use crate::matrix::Mat4;
+use crate::mesh::Vertex;
+use crate::raster::Fragment;
+use crate::shadow::ShadowMap;
+use crate::texture::{Sampler, Texture};
+use crate::vertex_batch::transform_to_clip;
+
+// シェーダに渡す描画中のメッシュと視点の情報
+pub struct Uniforms<'a> {
+    pub model: Mat4,
+    // 法線用のモデル行列 (Mat4::normal_matrix)
+    pub normal_matrix: Mat4,
+    pub mvp: Mat4,
+    pub camera_position: Vec3,
+    pub texture: Option<&'a Texture>,
+    pub sampler: Sampler,
+    // ライトごとのシャドウマップ (影を落とさないライトは None)
+    pub shadows: &'a [Option<ShadowMap>],
+}
+
+impl Uniforms<'_> {
+    // light 番目のライトの光がワールド座標に届く割合。シャドウマップがなければ 1
+    pub fn shadow_visibility(&self, light: usize, world_position: Vec3) -> f32 {
+        match self.shadows.get(light) {
+            Some(Some(shadow)) => shadow.visibility(world_position),
+            _ => 1.0,
+        }
+    }
+}
+
+// すべてのライトで albedo の面が反射する光を影を考慮して足し合わせる
+fn sum_lighting(
+    lights: &[Light],
+    position: Vec3,
+    normal: Vec3,
+    material: &Material,
+    albedo: Color,
+    uniforms: &Uniforms,
+) -> Vec3 {
+    let camera_position = uniforms.camera_position;
+    let mut total = Vec3::new(0.0, 0.0, 0.0);
+    for (index, light) in lights.iter().enumerate() {
+        let lighting =
+            light.calculate_lighting(position, normal, camera_position, material, albedo);
+        if lighting.x + lighting.y + lighting.z <= 0.0 {
+            continue;
+        }
+        total += lighting * uniforms.shadow_visibility(index, position);
+    }
+    total
+}
+
+// 頂点ステージからフラグメントステージへ補間して渡す値
+pub trait Varyings: Copy {
+    fn interpolate(values: &[Self; 3], fragment: &Fragment) -> Self;
+}
+
+impl Varyings for () {
+    fn interpolate(_: &[Self; 3], _: &Fragment) -> Self {}
+}
+
+impl Varyings for f32 {
+    fn interpolate(values: &[Self; 3], fragment: &Fragment) -> Self {
+        fragment.interpolate(*values)
+    }
+}
+
+impl Varyings for Vec2 {
+    fn interpolate(values: &[Self; 3], fragment: &Fragment) -> Self {
+        fragment.interpolate_vec2(*values)
+    }
+}
+
+impl Varyings for Vec3 {
+    fn interpolate(values: &[Self; 3], fragment: &Fragment) -> Self {
+        fragment.interpolate_vec3(*values)
+    }
+}
+
+impl Varyings for Color {
+    fn interpolate(values: &[Self; 3], fragment: &Fragment) -> Self {
+        fragment.interpolate_color(*values)
+    }
+}
+
+impl<A: Varyings, B: Varyings> Varyings for (A, B) {
+    fn interpolate(values: &[Self; 3], fragment: &Fragment) -> Self {
+        (
+            A::interpolate(&values.map(|v| v.0), fragment),
+            B::interpolate(&values.map(|v| v.1), fragment),
+        )
+    }
+}
+
+impl<A: Varyings, B: Varyings, C: Varyings> Varyings for (A, B, C) {
+    fn interpolate(values: &[Self; 3], fragment: &Fragment) -> Self {
+        (
+            A::interpolate(&values.map(|v| v.0), fragment),
+            B::interpolate(&values.map(|v| v.1), fragment),
+            C::interpolate(&values.map(|v| v.2), fragment),
+        )
+    }
+}
+
+impl<A: Varyings, B: Varyings, C: Varyings, D: Varyings> Varyings for (A, B, C, D) {
+    fn interpolate(values: &[Self; 3], fragment: &Fragment) -> Self {
+        (
+            A::interpolate(&values.map(|v| v.0), fragment),
+            B::interpolate(&values.map(|v| v.1), fragment),
+            C::interpolate(&values.map(|v| v.2), fragment),
+            D::interpolate(&values.map(|v| v.3), fragment),
+        )
+    }
+}
+
+// フラグメントステージの入力
+pub struct FragmentInput<'a, V> {
+    pub varyings: &'a V,
+    // 三角形に設定された色 (マテリアルの基本色を掛けたもの)
+    pub face_color: Color,
+    // 三角形に使うマテリアル
+    pub material: &'a Material,
+    // 2x2 ブロック全体の varyings (微分用)
+    pub quad: &'a [V; 4],
+}
+
+impl<V> FragmentInput<'_, V> {
+    // ブロック内の横・縦の差分で近似した画面空間微分
+    pub fn derivatives(&self, value: impl Fn(&V) -> Vec2) -> (Vec2, Vec2) {
+        let origin = value(&self.quad[0]);
+        let right = value(&self.quad[1]);
+        let below = value(&self.quad[2]);
+        (
+            Vec2::new(right.x - origin.x, right.y - origin.y),
+            Vec2::new(below.x - origin.x, below.y - origin.y),
+        )
+    }
+}
+
+// プログラマブルシェーダ
+pub trait Shader {
+    type Varyings: Varyings;
+
+    // メッシュの全頂点のクリップ座標 [x, y, z, w] で out の中身を置き換える。
+    // 既定では mvp でまとめて変換する。頂点を動かすシェーダはこれを上書きする
+    fn positions(&self, vertices: &[Vertex], uniforms: &Uniforms, out: &mut Vec<[f32; 4]>) {
+        transform_to_clip(&uniforms.mvp, vertices, out);
+    }
+
+    // 頂点ごとに補間する値を返す
+    fn vertex(&self, vertex: &Vertex, uniforms: &Uniforms) -> Self::Varyings;
+
+    // None を返すとそのピクセルは破棄される (深度も書き込まない)
+    fn fragment(&self, input: &FragmentInput<Self::Varyings>, uniforms: &Uniforms)
+    -> Option<Color>;
+}
+
+// 法線を法線行列で変換して正規化する
+pub fn transform_normal(normal_matrix: &Mat4, normal: Vec3) -> Vec3 {
+    normal_matrix.transform_vector(normal).normalize()
+}
+
+// 三角形の色とテクスチャだけで塗る既定のシェーダ
+pub struct UnlitShader;
+
+impl Shader for UnlitShader {
+    type Varyings = Vec2;
+
+    fn vertex(&self, vertex: &Vertex, _: &Uniforms) -> Vec2 {
+        vertex.uv
+    }
+
+    fn fragment(&self, input: &FragmentInput<Vec2>, uniforms: &Uniforms) -> Option<Color> {
+        match uniforms.texture {
+            Some(texture) => {
+                // ミップレベルはブロック単位の UV 微分から選ぶ
+                let (duv_dx, duv_dy) = input.derivatives(|uv| *uv);
+                let texel = texture.sample_grad(*input.varyings, duv_dx, duv_dy, &uniforms.sampler);
+                Some(texel.modulate(&input.face_color))
+            }
+            None => Some(input.face_color),
+        }
+    }
+}
+
+// ワールド空間の法線を RGB で表示するデバッグ用シェーダ
+pub struct NormalShader;
+
+impl Shader for NormalShader {
+    type Varyings = Vec3;
+
+    fn vertex(&self, vertex: &Vertex, uniforms: &Uniforms) -> Vec3 {
+        transform_normal(&uniforms.normal_matrix, vertex.normal)
+    }
+
+    fn fragment(&self, input: &FragmentInput<Vec3>, _: &Uniforms) -> Option<Color> {
+        let n = input.varyings.normalize();
+        let channel = |v: f32| ((v * 0.5 + 0.5) * 255.0).clamp(0.0, 255.0) as u8;
+        Some(Color::new(channel(n.x), channel(n.y), channel(n.z)))
+    }
+}
+
+// ライトごとに拡散・鏡面反射を計算して足し合わせ、シャドウマップの影を落とす
+pub struct LitShader<'a> {
+    pub lights: &'a [Light],
+}
+
+// ライトが当たらない面の明るさ
+const LIT_AMBIENT: f32 = 0.15;
+
+impl Shader for LitShader<'_> {
+    // (ワールド座標, ワールド法線, UV)
+    type Varyings = (Vec3, Vec3, Vec2);
+
+    fn vertex(&self, vertex: &Vertex, uniforms: &Uniforms) -> Self::Varyings {
+        (
+            uniforms.model.transform_point(vertex.position),
+            transform_normal(&uniforms.normal_matrix, vertex.normal),
+            vertex.uv,
+        )
+    }
+
+    fn fragment(
+        &self,
+        input: &FragmentInput<Self::Varyings>,
+        uniforms: &Uniforms,
+    ) -> Option<Color> {
+        let (position, normal, uv) = *input.varyings;
+
+        let base = match uniforms.texture {
+            Some(texture) => {
+                let (duv_dx, duv_dy) = input.derivatives(|v| v.2);
+                texture
+                    .sample_grad(uv, duv_dx, duv_dy, &uniforms.sampler)
+                    .modulate(&input.face_color)
+            }
+            None => input.face_color,
+        };
+
+        let material = input.material;
+        let normal = normal.normalize();
+        let lighting = sum_lighting(self.lights, position, normal, material, base, uniforms);
+        let emissive = material.emissive;
+        let channel = |albedo: u8, light: f32, emissive: u8| {
+            let value =
+                albedo as f32 * LIT_AMBIENT + light * (1.0 - LIT_AMBIENT) * 255.0 + emissive as f32;
+            value.clamp(0.0, 255.0) as u8
+        };
+        Some(Color::new(
+            channel(base.r, lighting.x, emissive.r),
+            channel(base.g, lighting.y, emissive.g),
+            channel(base.b, lighting.z, emissive.b),
+        ))
+    }
+}
+
+// トゥーン (セル) シェーディング。ライティングを bands 段階に量子化する
+pub struct ToonShader<'a> {
+    pub lights: &'a [Light],
+    pub bands: u32,
+}
+
+// 一番暗い段でも残す明るさ
+const TOON_AMBIENT: f32 = 0.25;
+
+// 0..1 の明るさ c を等幅の bands 段に切り捨て、TOON_AMBIENT..1 に割り当てる。
+// 1 段なら全部同じ明るさ (1.0) になる
+pub fn toon_level(c: f32, bands: u32) -> f32 {
+    let bands = bands.max(1);
+    if bands == 1 {
+        return 1.0;
+    }
+    let top = (bands - 1) as f32;
+    let level = (c.clamp(0.0, 1.0) * bands as f32).floor().min(top) / top;
+    TOON_AMBIENT + (1.0 - TOON_AMBIENT) * level
+}
+
+impl Shader for ToonShader<'_> {
+    // (ワールド座標, ワールド法線, UV)
+    type Varyings = (Vec3, Vec3, Vec2);
+
+    fn vertex(&self, vertex: &Vertex, uniforms: &Uniforms) -> Self::Varyings {
+        (
+            uniforms.model.transform_point(vertex.position),
+            transform_normal(&uniforms.normal_matrix, vertex.normal),
+            vertex.uv,
+        )
+    }
+
+    fn fragment(
+        &self,
+        input: &FragmentInput<Self::Varyings>,
+        uniforms: &Uniforms,
+    ) -> Option<Color> {
+        let (position, normal, uv) = *input.varyings;
+
+        // 白い面で求めた明るさを RGB ごとに段階へ丸める
+        let material = input.material;
+        let normal = normal.normalize();
+        let lighting = sum_lighting(
+            self.lights,
+            position,
+            normal,
+            material,
+            Color::WHITE,
+            uniforms,
+        );
+        let level = |c: f32| toon_level(c, self.bands);
+
+        let base = match uniforms.texture {
+            Some(texture) => {
+                let (duv_dx, duv_dy) = input.derivatives(|v| v.2);
+                texture
+                    .sample_grad(uv, duv_dx, duv_dy, &uniforms.sampler)
+                    .modulate(&input.face_color)
+            }
+            None => input.face_color,
+        };
+        let shaded = base.multiply_rgb(Vec3::new(
+            level(lighting.x),
+            level(lighting.y),
+            level(lighting.z),
+        ));
+        Some(shaded.saturating_add(&material.emissive))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn toon_level_band_boundaries() {
+        // 4 段なら [0, 0.25), [0.25, 0.5), [0.5, 0.75), [0.75, 1] が 0..3 段目
+        let step = (1.0 - TOON_AMBIENT) / 3.0;
+        let band = |n: f32| TOON_AMBIENT + step * n;
+        assert_eq!(toon_level(0.0, 4), TOON_AMBIENT);
+        assert_eq!(toon_level(0.2499, 4), TOON_AMBIENT);
+        assert_eq!(toon_level(0.25, 4), band(1.0));
+        assert_eq!(toon_level(0.4999, 4), band(1.0));
+        assert_eq!(toon_level(0.5, 4), band(2.0));
+        assert_eq!(toon_level(0.7499, 4), band(2.0));
+        assert_eq!(toon_level(0.75, 4), 1.0);
+        assert_eq!(toon_level(1.0, 4), 1.0);
+
+        // ちょうど bands 種類の明るさになる
+        for bands in 1..8 {
+            let mut levels: Vec<f32> =
+                (0..=1000).map(|i| toon_level(i as f32 / 1000.0, bands)).collect();
+            levels.dedup();
+            assert_eq!(levels.len(), bands as usize);
+        }
+    }
+
+    #[test]
+    fn toon_level_clamps_input_and_band_count() {
+        assert_eq!(toon_level(-0.5, 3), TOON_AMBIENT);
+        assert_eq!(toon_level(2.0, 3), 1.0);
+        // 0 段は 1 段として扱い、全部同じ明るさ
+        assert_eq!(toon_level(0.1, 0), 1.0);
+        assert_eq!(toon_level(0.9, 1), 1.0);
+        assert_eq!(toon_level(0.0, 0), 1.0);
+    }
+
+    #[test]
+    fn toon_level_is_monotonic() {
+        for bands in 1..8 {
+            let mut previous = toon_level(0.0, bands);
+            let mut levels = 1;
+            for i in 1..=1000 {
+                let level = toon_level(i as f32 / 1000.0, bands);
+                assert!(level >= previous);
+                if level > previous {
+                    levels += 1;
+                }
+                previous = level;
+            }
+            assert_eq!(levels, bands);
+        }
+    }
+}
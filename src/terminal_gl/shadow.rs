@@ -0,0 +1,262 @@
+use crate::geometry::Vec3;
+use crate::matrix::Mat4;
+use crate::mesh::Mesh;
+use crate::raster::rasterize_clipped;
+use crate::vertex_batch::transform_to_clip;
+
+// ライトから見た深度を保持するオフスクリーンのバッファ
+pub struct ShadowMap {
+    size: usize,
+    depth: Vec<f32>,
+    light_view_projection: Mat4,
+    // メッシュの頂点をクリップ座標に変換する作業用のバッファ
+    clip: Vec<[f32; 4]>,
+    // 自分自身の面で影にならないよう比較する深度を手前にずらす量
+    pub bias: f32,
+    // PCF で平均するテクセルの半径 (0 で PCF なし)
+    pub pcf_radius: u32,
+}
+
+impl ShadowMap {
+    pub fn new(size: usize) -> Self {
+        Self {
+            size,
+            depth: vec![f32::INFINITY; size * size],
+            light_view_projection: Mat4::identity(),
+            clip: Vec::new(),
+            bias: 0.005,
+            pcf_radius: 1,
+        }
+    }
+
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    // ライトの視点から (メッシュ, モデル行列) の深度を描く。裏面も影を落とすのでカリングしない
+    pub fn render<'a>(
+        &mut self,
+        light_view_projection: Mat4,
+        meshes: impl Iterator<Item = (&'a Mesh, &'a Mat4)>,
+    ) {
+        self.light_view_projection = light_view_projection;
+        self.depth.fill(f32::INFINITY);
+
+        let size = self.size;
+        for (mesh, model) in meshes {
+            let mvp = light_view_projection.multiply(model);
+            transform_to_clip(&mvp, &mesh.vertices, &mut self.clip);
+
+            let clip = &self.clip;
+            for triangle in &mesh.triangles {
+                let depth = &mut self.depth;
+                rasterize_clipped(triangle.vertices.map(|i| clip[i]), size, size, |quad| {
+                    for (fragment, covered) in quad.fragments.iter().zip(quad.coverage) {
+                        let idx = fragment.y as usize * size + fragment.x as usize;
+                        if covered && fragment.depth < depth[idx] {
+                            depth[idx] = fragment.depth;
+                        }
+                    }
+                });
+            }
+        }
+    }
+
+    // world_position に光が届く割合 (0 で完全に影、1 で影なし)
+    pub fn visibility(&self, world_position: Vec3) -> f32 {
+        let clip = self
+            .light_view_projection
+            .transform_homogeneous(world_position);
+        if clip[3] <= 0.0 {
+            return 1.0;
+        }
+        let ndc = [clip[0] / clip[3], clip[1] / clip[3], clip[2] / clip[3]];
+        if ndc.iter().any(|v| v.abs() > 1.0) {
+            // ライトの視野の外は照らされているものとする
+            return 1.0;
+        }
+
+        let size = self.size as f32;
+        let x = ((ndc[0] + 1.0) * 0.5 * size) as i32;
+        let y = ((1.0 - ndc[1]) * 0.5 * size) as i32;
+        let depth = ndc[2] * 0.5 + 0.5 - self.bias;
+
+        let radius = self.pcf_radius as i32;
+        let mut lit = 0;
+        let mut total = 0;
+        for dy in -radius..=radius {
+            for dx in -radius..=radius {
+                let sx = (x + dx).clamp(0, self.size as i32 - 1) as usize;
+                let sy = (y + dy).clamp(0, self.size as i32 - 1) as usize;
+                if depth <= self.depth[sy * self.size + sx] {
+                    lit += 1;
+                }
+                total += 1;
+            }
+        }
+        lit as f32 / total as f32
+    }
+}
+
+// 影を落とすライトの投影
+#[derive(Clone, Copy, Debug)]
+pub enum ShadowProjection {
+    // スポットライト: ライト位置からの透視投影 (fov はラジアン)
+    Perspective { fov: f32, near: f32, far: f32 },
+    // 平行光源: target を中心とした half_size 四方の正射影
+    Orthographic { half_size: f32, near: f32, far: f32 },
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct ShadowSettings {
+    pub projection: ShadowProjection,
+    // 平行光源では影を落とす範囲の中心、点光源ではライトを向ける点 (スポットライトでは使わない)
+    pub target: Vec3,
+    // シャドウマップの一辺のテクセル数
+    pub resolution: usize,
+    pub bias: f32,
+    pub pcf_radius: u32,
+}
+
+impl ShadowProjection {
+    pub fn far(&self) -> f32 {
+        match *self {
+            ShadowProjection::Perspective { far, .. }
+            | ShadowProjection::Orthographic { far, .. } => far,
+        }
+    }
+}
+
+impl ShadowSettings {
+    pub fn new(projection: ShadowProjection, target: Vec3) -> Self {
+        Self {
+            projection,
+            target,
+            resolution: 512,
+            bias: 0.005,
+            pcf_radius: 1,
+        }
+    }
+
+    // eye から direction を向いたライトのビュー射影行列
+    pub fn view_projection(&self, eye: Vec3, direction: Vec3) -> Mat4 {
+        let direction = direction.normalize();
+        // 真上・真下を向くときは別の軸を上にする
+        let up = if direction.y.abs() > 0.99 {
+            Vec3::new(0.0, 0.0, 1.0)
+        } else {
+            Vec3::new(0.0, 1.0, 0.0)
+        };
+        let view = Mat4::look_at(eye, eye + direction, up);
+        let projection = match self.projection {
+            ShadowProjection::Perspective { fov, near, far } => {
+                Mat4::perspective(fov, 1.0, near, far)
+            }
+            ShadowProjection::Orthographic {
+                half_size,
+                near,
+                far,
+            } => Mat4::orthographic(-half_size, half_size, -half_size, half_size, near, far),
+        };
+        projection.multiply(&view)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 真上から原点を中心に 6x6 の範囲を見下ろす平行光源
+    fn overhead_light() -> Mat4 {
+        let settings = ShadowSettings::new(
+            ShadowProjection::Orthographic {
+                half_size: 3.0,
+                near: 0.1,
+                far: 10.0,
+            },
+            Vec3::new(0.0, 0.0, 0.0),
+        );
+        settings.view_projection(Vec3::new(0.0, 5.0, 0.0), Vec3::new(0.0, -1.0, 0.0))
+    }
+
+    // y = 0 の床と、その上 y = 1 に浮かぶ 1x1 の板
+    fn overhead_shadow_map(size: usize, pcf_radius: u32) -> ShadowMap {
+        let floor = Mesh::create_plane(6.0);
+        let occluder = Mesh::create_plane(1.0);
+        let identity = Mat4::identity();
+        let raised = Mat4::translation(0.0, 1.0, 0.0);
+
+        let mut map = ShadowMap::new(size);
+        map.pcf_radius = pcf_radius;
+        map.render(
+            overhead_light(),
+            [(&floor, &identity), (&occluder, &raised)].into_iter(),
+        );
+        map
+    }
+
+    #[test]
+    fn lit_and_occluded_points() {
+        let map = overhead_shadow_map(64, 0);
+        assert_eq!(map.visibility(Vec3::new(2.0, 0.0, 2.0)), 1.0);
+        assert_eq!(map.visibility(Vec3::new(-1.5, 0.0, 0.3)), 1.0);
+        // 板の真下は影、板の上面は照らされる
+        assert_eq!(map.visibility(Vec3::new(0.0, 0.0, 0.0)), 0.0);
+        assert_eq!(map.visibility(Vec3::new(0.2, 0.0, -0.3)), 0.0);
+        assert_eq!(map.visibility(Vec3::new(0.2, 1.0, -0.3)), 1.0);
+        // ライトの視野の外は照らされているものとする
+        assert_eq!(map.visibility(Vec3::new(10.0, 0.0, 0.0)), 1.0);
+    }
+
+    #[test]
+    fn bias_prevents_self_shadowing_acne() {
+        // 光に対して 45° 傾いた面は、テクセル内の位置によってテクセル中心の深度より奥になる
+        let slope = Mesh::create_plane(4.0);
+        let model = Mat4::rotation_x(45.0_f32.to_radians());
+        let mut map = ShadowMap::new(128);
+        map.pcf_radius = 0;
+        map.render(overhead_light(), [(&slope, &model)].into_iter());
+
+        let surface_points: Vec<Vec3> = (0..20)
+            .flat_map(|i| (0..20).map(move |j| (i, j)))
+            .map(|(i, j)| {
+                let u = -1.5 + 3.0 * i as f32 / 19.0;
+                let v = -1.5 + 3.0 * j as f32 / 19.0;
+                model.transform_point(Vec3::new(u, 0.0, v))
+            })
+            .collect();
+        let shadowed = |map: &ShadowMap| {
+            surface_points
+                .iter()
+                .filter(|&&p| map.visibility(p) < 1.0)
+                .count()
+        };
+
+        map.bias = 0.0;
+        assert!(shadowed(&map) > 0);
+        map.bias = 0.005;
+        assert_eq!(shadowed(&map), 0);
+    }
+
+    #[test]
+    fn pcf_gives_fractional_visibility_at_shadow_edge() {
+        let map = overhead_shadow_map(64, 1);
+        assert_eq!(map.visibility(Vec3::new(0.0, 0.0, 0.0)), 0.0);
+        assert_eq!(map.visibility(Vec3::new(2.0, 0.0, 0.0)), 1.0);
+
+        // 板の縁 x = 0.5 をまたいで床を横切ると、0 と 1 の間の値を通って単調に明るくなる
+        let mut previous = 0.0;
+        let mut fractional = 0;
+        for i in 0..=40 {
+            let x = 0.2 + 0.6 * i as f32 / 40.0;
+            let visibility = map.visibility(Vec3::new(x, 0.0, 0.0));
+            assert!(visibility >= previous);
+            if visibility > 0.0 && visibility < 1.0 {
+                fractional += 1;
+            }
+            previous = visibility;
+        }
+        assert!(fractional > 0);
+        assert_eq!(previous, 1.0);
+    }
+}
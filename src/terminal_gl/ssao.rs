@@ -0,0 +1,279 @@
+use crate::camera::Camera;
+use crate::geometry::Vec3;
+use crate::terminal_gl::Canvas;
+use std::f32::consts::PI;
+
+// スクリーン空間アンビエントオクルージョンの設定
+#[derive(Clone, Copy, Debug)]
+pub struct SsaoSettings {
+    // 1 ピクセルあたりのサンプル数。
+    // 200x200 の画面全体に面があるとき、release ビルドで 8 サンプルなら 1 フレーム約 5ms、16 で約 8ms
+    pub samples: usize,
+    // 遮るものを探す半球の半径 (ワールド単位)
+    pub radius: f32,
+    // 完全に遮られたときに暗くする割合 (0..1)
+    pub intensity: f32,
+    // 同じ面の上の点を遮蔽と判定しないための深さの余裕 (ワールド単位)
+    pub bias: f32,
+}
+
+impl Default for SsaoSettings {
+    fn default() -> Self {
+        Self {
+            samples: 8,
+            radius: 0.5,
+            intensity: 0.8,
+            bias: 0.02,
+        }
+    }
+}
+
+// ノイズでサンプルの向きを回すタイルの大きさ。ぼかしも同じ大きさで行う
+const NOISE_SIZE: usize = 4;
+
+// 4x4 の Bayer 行列 (ピクセルごとのサンプルの回転角に使う)
+const BAYER: [[u8; NOISE_SIZE]; NOISE_SIZE] =
+    [[0, 8, 2, 10], [12, 4, 14, 6], [3, 11, 1, 9], [15, 7, 13, 5]];
+
+// 深度バッファからビュー空間の位置と法線を復元し、周りの面に遮られる割合で暗くする。
+// 作業用のバッファは大きさが変わるまで使い回す
+pub struct Ssao {
+    kernel: Vec<Vec3>,
+    positions: Vec<Option<Vec3>>,
+    occlusion: Vec<f32>,
+    row_sums: Vec<(f32, f32)>,
+}
+
+impl Ssao {
+    pub fn new() -> Self {
+        Self {
+            kernel: Vec::new(),
+            positions: Vec::new(),
+            occlusion: Vec::new(),
+            row_sums: Vec::new(),
+        }
+    }
+
+    pub fn apply(&mut self, canvas: &mut Canvas, camera: &Camera, settings: &SsaoSettings) {
+        let (width, height) = (canvas.width, canvas.height);
+        let samples = settings.samples.max(1);
+        if self.kernel.len() != samples {
+            self.kernel = hemisphere_kernel(samples);
+        }
+
+        let tan_y = (camera.fov * 0.5).tan();
+        let tan_x = tan_y * camera.aspect_ratio;
+        let (near, far) = (camera.near_plane, camera.far_plane);
+
+        // [0, 1] の深度からビュー空間 (カメラは -z を向く) の位置を復元する
+        self.positions.clear();
+        self.positions.extend((0..width * height).map(|idx| {
+            let depth = canvas.depth[idx];
+            if !depth.is_finite() {
+                return None;
+            }
+            let ndc_z = depth * 2.0 - 1.0;
+            let distance = 2.0 * near * far / (far + near - ndc_z * (far - near));
+            let ndc_x = ((idx % width) as f32 + 0.5) / width as f32 * 2.0 - 1.0;
+            let ndc_y = 1.0 - ((idx / width) as f32 + 0.5) / height as f32 * 2.0;
+            Some(Vec3::new(
+                ndc_x * tan_x * distance,
+                ndc_y * tan_y * distance,
+                -distance,
+            ))
+        }));
+
+        self.occlusion.clear();
+        self.occlusion.resize(width * height, 0.0);
+        for y in 0..height {
+            for x in 0..width {
+                let Some(position) = self.positions[y * width + x] else {
+                    continue;
+                };
+                let Some(normal) = self.normal_at(x, y, width, height) else {
+                    continue;
+                };
+
+                // 法線まわりにピクセルごとに回した接空間で半球のサンプルを置く
+                let angle = BAYER[y % NOISE_SIZE][x % NOISE_SIZE] as f32 / 16.0 * 2.0 * PI;
+                let random = Vec3::new(angle.cos(), angle.sin(), 0.0);
+                let tangent = (random - normal * random.dot(&normal)).normalize();
+                let bitangent = normal.cross(&tangent);
+
+                let mut occluded = 0.0;
+                for k in &self.kernel {
+                    let offset = tangent * k.x + bitangent * k.y + normal * k.z;
+                    let sample = position + offset * settings.radius;
+                    if sample.z >= 0.0 {
+                        continue;
+                    }
+
+                    let ndc_x = sample.x / (-sample.z * tan_x);
+                    let ndc_y = sample.y / (-sample.z * tan_y);
+                    let sx = ((ndc_x + 1.0) * 0.5 * width as f32).floor();
+                    let sy = ((1.0 - ndc_y) * 0.5 * height as f32).floor();
+                    if sx < 0.0 || sy < 0.0 || sx >= width as f32 || sy >= height as f32 {
+                        continue;
+                    }
+                    let Some(surface) = self.positions[sy as usize * width + sx as usize] else {
+                        continue;
+                    };
+
+                    // サンプルより手前に面があれば遮られている。
+                    // 半径より大きく離れた手前の物体ほど影響を小さくする
+                    if surface.z >= sample.z + settings.bias {
+                        let range = settings.radius / (position.z - surface.z).abs().max(1e-6);
+                        occluded += range.min(1.0);
+                    }
+                }
+                self.occlusion[y * width + x] = occluded / samples as f32;
+            }
+        }
+
+        // ノイズのタイルと同じ大きさでぼかしてから色に掛ける。
+        // 背景のピクセルを除いた平均なので (遮蔽の和, 個数) を横・縦の順に足す
+        let half = NOISE_SIZE / 2;
+        self.row_sums.clear();
+        for y in 0..height {
+            for x in 0..width {
+                let mut sum = (0.0, 0.0);
+                for bx in x.saturating_sub(half)..(x + half).min(width) {
+                    if self.positions[y * width + bx].is_some() {
+                        sum.0 += self.occlusion[y * width + bx];
+                        sum.1 += 1.0;
+                    }
+                }
+                self.row_sums.push(sum);
+            }
+        }
+        for y in 0..height {
+            for x in 0..width {
+                let idx = y * width + x;
+                if self.positions[idx].is_none() {
+                    continue;
+                }
+                let (mut sum, mut count) = (0.0, 0.0);
+                for by in y.saturating_sub(half)..(y + half).min(height) {
+                    let (row_sum, row_count) = self.row_sums[by * width + x];
+                    sum += row_sum;
+                    count += row_count;
+                }
+                let factor = 1.0 - settings.intensity * sum / count;
+                canvas.r[idx] = (canvas.r[idx] as f32 * factor) as u8;
+                canvas.g[idx] = (canvas.g[idx] as f32 * factor) as u8;
+                canvas.b[idx] = (canvas.b[idx] as f32 * factor) as u8;
+            }
+        }
+    }
+
+    // 隣のピクセルの位置との差から求めた、カメラ側を向く法線。
+    // 物体の輪郭をまたがないよう、左右・上下のうち深さの近い方を使う
+    fn normal_at(&self, x: usize, y: usize, width: usize, height: usize) -> Option<Vec3> {
+        let center = self.positions[y * width + x]?;
+        let neighbor = |nx: usize, ny: usize| self.positions[ny * width + nx];
+        let pick = |before: Option<Vec3>, after: Option<Vec3>| {
+            let backward = before.map(|p| center - p);
+            let forward = after.map(|p| p - center);
+            match (backward, forward) {
+                (Some(a), Some(b)) => Some(if a.z.abs() < b.z.abs() { a } else { b }),
+                (a, b) => a.or(b),
+            }
+        };
+
+        let left = (x > 0).then(|| neighbor(x - 1, y)).flatten();
+        let right = (x + 1 < width).then(|| neighbor(x + 1, y)).flatten();
+        let up = (y > 0).then(|| neighbor(x, y - 1)).flatten();
+        let down = (y + 1 < height).then(|| neighbor(x, y + 1)).flatten();
+        let dx = pick(left, right)?;
+        let dy = pick(up, down)?;
+
+        let normal = dx.cross(&dy).normalize();
+        // 位置から視点への向きと同じ側に向ける
+        if normal.dot(&center) > 0.0 {
+            Some(-normal)
+        } else {
+            Some(normal)
+        }
+    }
+}
+
+impl Default for Ssao {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// z > 0 の半球の中の点。Fibonacci 螺旋で向きを散らし、中心の近くに多く置く
+fn hemisphere_kernel(samples: usize) -> Vec<Vec3> {
+    let golden_angle = PI * (3.0 - 5.0_f32.sqrt());
+    (0..samples)
+        .map(|i| {
+            let t = (i as f32 + 0.5) / samples as f32;
+            let z = 1.0 - t;
+            let r = (1.0 - z * z).sqrt();
+            let phi = golden_angle * i as f32;
+            let fraction = (i + 1) as f32 / samples as f32;
+            let scale = 0.1 + 0.9 * fraction * fraction;
+            Vec3::new(phi.cos() * r * scale, phi.sin() * r * scale, z * scale)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::matrix::Mat4;
+    use crate::mesh::Mesh;
+    use crate::vertex_batch::transform_to_clip;
+
+    const SIZE: usize = 64;
+
+    // (メッシュ, モデル行列) の深度を描いた白いキャンバスに SSAO を掛け、各ピクセルの明るさを返す
+    fn shade(camera: &Camera, meshes: &[(Mesh, Mat4)]) -> Vec<u8> {
+        let mut canvas = Canvas::new(SIZE, SIZE);
+        canvas.clear_with(255, 255, 255);
+        let view_projection = camera.get_view_projection_matrix();
+        let mut clip = Vec::new();
+        for (mesh, model) in meshes {
+            transform_to_clip(&view_projection.multiply(model), &mesh.vertices, &mut clip);
+            mesh.render_depth(&mut canvas, &clip);
+        }
+        Ssao::new().apply(&mut canvas, camera, &SsaoSettings::default());
+        canvas.r
+    }
+
+    fn camera() -> Camera {
+        let mut camera = Camera::new(SIZE as f32, SIZE as f32);
+        camera.set_position(Vec3::new(0.0, 3.0, 1.0));
+        camera.look_at(Vec3::new(0.0, 0.0, -1.0));
+        camera
+    }
+
+    #[test]
+    fn flat_plane_is_not_occluded() {
+        let floor = (Mesh::create_plane(40.0), Mat4::identity());
+        let shaded = shade(&camera(), &[floor]);
+        for (i, &value) in shaded.iter().enumerate() {
+            assert!(value >= 245, "pixel {i}: {value}");
+        }
+    }
+
+    #[test]
+    fn concave_corner_is_occluded() {
+        // 床と、z = -1 に立てた手前向きの壁
+        let floor = (Mesh::create_plane(40.0), Mat4::identity());
+        let wall = Mat4::translation(0.0, 0.0, -1.0).multiply(&Mat4::rotation_x(PI * 0.5));
+        let camera = camera();
+        let shaded = shade(&camera, &[floor, (Mesh::create_plane(40.0), wall)]);
+
+        // 床と壁の境目の行は、境目から離れた床や壁より暗い
+        let clip = camera
+            .get_view_projection_matrix()
+            .transform_homogeneous(Vec3::new(0.0, 0.0, -1.0));
+        let corner = ((1.0 - clip[1] / clip[3]) * 0.5 * SIZE as f32) as usize;
+        let row = |y: usize| shaded[y * SIZE + SIZE / 2];
+        assert!(row(corner) < 200, "corner: {}", row(corner));
+        assert!(row(SIZE - 4) >= 245);
+        assert!(row(3) >= 245);
+    }
+}
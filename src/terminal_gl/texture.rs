@@ -0,0 +1,475 @@
+use crate::geometry::{Color, Vec2, Vec3};
+use crate::image;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Filter {
+    // ドット絵のようなテクスチャ用
+    Nearest,
+    Bilinear,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum WrapMode {
+    Repeat,
+    Clamp,
+    Mirror,
+}
+
+impl WrapMode {
+    fn apply(&self, i: i32, size: usize) -> usize {
+        let n = size as i32;
+        let wrapped = match self {
+            WrapMode::Repeat => i.rem_euclid(n),
+            WrapMode::Clamp => i.clamp(0, n - 1),
+            WrapMode::Mirror => {
+                let m = i.rem_euclid(2 * n);
+                if m >= n { 2 * n - 1 - m } else { m }
+            }
+        };
+        wrapped as usize
+    }
+}
+
+// ミップレベルの選び方。Linear と Filter::Bilinear の組み合わせがトライリニア
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MipFilter {
+    // ミップマップを使わない。Sampler の既定は Nearest
+    None,
+    Nearest,
+    Linear,
+}
+
+// テクスチャのサンプリング方法
+#[derive(Clone, Copy, Debug)]
+pub struct Sampler {
+    pub filter: Filter,
+    pub mip_filter: MipFilter,
+    pub wrap_u: WrapMode,
+    pub wrap_v: WrapMode,
+}
+
+impl Sampler {
+    pub fn new(filter: Filter, wrap: WrapMode) -> Self {
+        Self {
+            filter,
+            mip_filter: MipFilter::Nearest,
+            wrap_u: wrap,
+            wrap_v: wrap,
+        }
+    }
+
+    pub fn trilinear(wrap: WrapMode) -> Self {
+        Self {
+            mip_filter: MipFilter::Linear,
+            ..Self::new(Filter::Bilinear, wrap)
+        }
+    }
+}
+
+impl Default for Sampler {
+    fn default() -> Self {
+        Self::new(Filter::Bilinear, WrapMode::Repeat)
+    }
+}
+
+#[derive(Clone)]
+struct MipLevel {
+    width: usize,
+    height: usize,
+    pixels: Vec<Color>,
+}
+
+impl MipLevel {
+    fn texel(&self, x: usize, y: usize) -> Color {
+        self.pixels[y * self.width + x]
+    }
+
+    // 2x2 の平均で半分の大きさのレベルを作る (奇数サイズは端を複製)
+    fn downsample(&self) -> MipLevel {
+        let width = (self.width / 2).max(1);
+        let height = (self.height / 2).max(1);
+        let mut pixels = Vec::with_capacity(width * height);
+
+        for y in 0..height {
+            for x in 0..width {
+                let x0 = (x * 2).min(self.width - 1);
+                let x1 = (x * 2 + 1).min(self.width - 1);
+                let y0 = (y * 2).min(self.height - 1);
+                let y1 = (y * 2 + 1).min(self.height - 1);
+                let texels = [
+                    self.texel(x0, y0),
+                    self.texel(x1, y0),
+                    self.texel(x0, y1),
+                    self.texel(x1, y1),
+                ];
+                let average = |channel: fn(&Color) -> u8| {
+                    ((texels.iter().map(|c| channel(c) as u32).sum::<u32>() + 2) / 4) as u8
+                };
+                pixels.push(Color::new(
+                    average(|c| c.r),
+                    average(|c| c.g),
+                    average(|c| c.b),
+                ));
+            }
+        }
+
+        MipLevel {
+            width,
+            height,
+            pixels,
+        }
+    }
+
+    fn sample(&self, u: f32, v: f32, sampler: &Sampler) -> Color {
+        let u = u * self.width as f32;
+        let v = (1.0 - v) * self.height as f32;
+
+        match sampler.filter {
+            Filter::Nearest => {
+                let x = sampler.wrap_u.apply(u.floor() as i32, self.width);
+                let y = sampler.wrap_v.apply(v.floor() as i32, self.height);
+                self.texel(x, y)
+            }
+            Filter::Bilinear => {
+                // テクセル中心を基準に 4 点を補間する
+                let u = u - 0.5;
+                let v = v - 0.5;
+                let x0 = u.floor();
+                let y0 = v.floor();
+                let fx = u - x0;
+                let fy = v - y0;
+
+                let xs = [
+                    sampler.wrap_u.apply(x0 as i32, self.width),
+                    sampler.wrap_u.apply(x0 as i32 + 1, self.width),
+                ];
+                let ys = [
+                    sampler.wrap_v.apply(y0 as i32, self.height),
+                    sampler.wrap_v.apply(y0 as i32 + 1, self.height),
+                ];
+
+                let top = self.texel(xs[0], ys[0]).lerp(&self.texel(xs[1], ys[0]), fx);
+                let bottom = self.texel(xs[0], ys[1]).lerp(&self.texel(xs[1], ys[1]), fx);
+                top.lerp(&bottom, fy)
+            }
+        }
+    }
+}
+
+// 行 0 が画像の上端。UV の v = 0 は下端 (OBJ と同じ向き)
+#[derive(Clone)]
+pub struct Texture {
+    pub width: usize,
+    pub height: usize,
+    // levels[0] が元画像、以降 1x1 まで半分ずつ縮小したミップチェーン
+    levels: Vec<MipLevel>,
+}
+
+impl Texture {
+    pub fn from_pixels(width: usize, height: usize, pixels: Vec<Color>) -> Self {
+        assert_eq!(pixels.len(), width * height);
+        assert!(width > 0 && height > 0);
+
+        let mut levels = vec![MipLevel {
+            width,
+            height,
+            pixels,
+        }];
+        while let Some(last) = levels.last().filter(|l| l.width > 1 || l.height > 1) {
+            let next = last.downsample();
+            levels.push(next);
+        }
+
+        Self {
+            width,
+            height,
+            levels,
+        }
+    }
+
+    // 市松模様 (size x size テクセル、一辺 cells マス)
+    pub fn checker(size: usize, cells: usize, a: Color, b: Color) -> Self {
+        let cell = (size / cells.max(1)).max(1);
+        let mut pixels = Vec::with_capacity(size * size);
+        for y in 0..size {
+            for x in 0..size {
+                pixels.push(if (x / cell + y / cell).is_multiple_of(2) {
+                    a
+                } else {
+                    b
+                });
+            }
+        }
+        Self::from_pixels(size, size, pixels)
+    }
+
+    // 格子模様 (一辺 cells マス、線の太さ line_width テクセル)
+    pub fn grid(
+        size: usize,
+        cells: usize,
+        line_width: usize,
+        line: Color,
+        background: Color,
+    ) -> Self {
+        let cell = (size / cells.max(1)).max(1);
+        let mut pixels = Vec::with_capacity(size * size);
+        for y in 0..size {
+            for x in 0..size {
+                let on_line = x % cell < line_width || y % cell < line_width;
+                pixels.push(if on_line { line } else { background });
+            }
+        }
+        Self::from_pixels(size, size, pixels)
+    }
+
+    // PPM / PNG / TGA を内容から判別して読み込む
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let data = fs::read(path)?;
+        image::decode(&data)
+    }
+
+    // 元画像のテクセル
+    #[cfg(test)]
+    pub fn texel(&self, x: usize, y: usize) -> Color {
+        self.levels[0].texel(x, y)
+    }
+
+    // ミップマップを使わずに元画像からサンプリングする
+    pub fn sample(&self, uv: Vec2, sampler: &Sampler) -> Color {
+        self.levels[0].sample(uv.x, uv.y, sampler)
+    }
+
+    // UV の画面空間微分 (隣のピクセルとの差) から詳細度を決めてサンプリングする
+    pub fn sample_grad(&self, uv: Vec2, duv_dx: Vec2, duv_dy: Vec2, sampler: &Sampler) -> Color {
+        let lod = self.lod(duv_dx, duv_dy);
+        self.sample_lod(uv, lod, sampler)
+    }
+
+    pub fn lod(&self, duv_dx: Vec2, duv_dy: Vec2) -> f32 {
+        let (w, h) = (self.width as f32, self.height as f32);
+        let dx = Vec2::new(duv_dx.x * w, duv_dx.y * h).length();
+        let dy = Vec2::new(duv_dy.x * w, duv_dy.y * h).length();
+        let rho = dx.max(dy);
+        if rho > 0.0 { rho.log2() } else { 0.0 }
+    }
+
+    pub fn sample_lod(&self, uv: Vec2, lod: f32, sampler: &Sampler) -> Color {
+        let max_level = (self.levels.len() - 1) as f32;
+        let lod = lod.clamp(0.0, max_level);
+
+        match sampler.mip_filter {
+            MipFilter::None => self.levels[0].sample(uv.x, uv.y, sampler),
+            MipFilter::Nearest => self.levels[lod.round() as usize].sample(uv.x, uv.y, sampler),
+            MipFilter::Linear => {
+                let lower = lod.floor();
+                let t = lod - lower;
+                let a = self.levels[lower as usize].sample(uv.x, uv.y, sampler);
+                if t <= 0.0 {
+                    return a;
+                }
+                let b = self.levels[lower as usize + 1].sample(uv.x, uv.y, sampler);
+                a.lerp(&b, t)
+            }
+        }
+    }
+}
+
+// キューブマップ (スカイボックス用)。faces は +X, -X, +Y, -Y, +Z, -Z の順で、
+// 各面は立方体の内側から見た向き (OpenGL と同じ並び)
+#[derive(Clone)]
+pub struct CubeMap {
+    faces: [Texture; 6],
+    pub sampler: Sampler,
+}
+
+impl CubeMap {
+    pub fn new(faces: [Texture; 6]) -> Self {
+        Self {
+            faces,
+            sampler: Sampler::new(Filter::Bilinear, WrapMode::Clamp),
+        }
+    }
+
+    pub fn load<P: AsRef<Path>>(paths: [P; 6]) -> io::Result<Self> {
+        let [px, nx, py, ny, pz, nz] = paths;
+        Ok(Self::new([
+            Texture::load(px)?,
+            Texture::load(nx)?,
+            Texture::load(py)?,
+            Texture::load(ny)?,
+            Texture::load(pz)?,
+            Texture::load(nz)?,
+        ]))
+    }
+
+    // 向き direction (正規化不要) の先にある色
+    pub fn sample(&self, direction: Vec3) -> Color {
+        let (x, y, z) = (direction.x, direction.y, direction.z);
+        let (ax, ay, az) = (x.abs(), y.abs(), z.abs());
+
+        // 一番大きい成分の面を選び、面上の (s, t) を求める。t は画像の上から下へ
+        let (face, s, t, major) = if ax >= ay && ax >= az {
+            if x > 0.0 { (0, -z, -y, ax) } else { (1, z, -y, ax) }
+        } else if ay >= az {
+            if y > 0.0 { (2, x, z, ay) } else { (3, x, -z, ay) }
+        } else if z > 0.0 {
+            (4, x, -y, az)
+        } else {
+            (5, -x, -y, az)
+        };
+        if major <= 0.0 {
+            return Color::BLACK;
+        }
+
+        let u = (s / major + 1.0) * 0.5;
+        let v = 1.0 - (t / major + 1.0) * 0.5;
+        self.faces[face].sample(Vec2::new(u, v), &self.sampler)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn gray(v: u8) -> Color {
+        Color::new(v, v, v)
+    }
+
+    // 4x2 のグレー。上の行が 0, 40, 80, 120、下の行が 200, 210, 220, 230
+    fn ramp() -> Texture {
+        let values = [0, 40, 80, 120, 200, 210, 220, 230];
+        Texture::from_pixels(4, 2, values.iter().map(|&v| gray(v)).collect())
+    }
+
+    fn sample(texture: &Texture, u: f32, v: f32, filter: Filter, wrap: WrapMode) -> u8 {
+        texture.sample(Vec2::new(u, v), &Sampler::new(filter, wrap)).r
+    }
+
+    #[test]
+    fn nearest_picks_the_texel_under_uv() {
+        let texture = ramp();
+        // v = 1 が上端の行
+        assert_eq!(sample(&texture, 0.1, 0.9, Filter::Nearest, WrapMode::Clamp), 0);
+        assert_eq!(sample(&texture, 0.6, 0.9, Filter::Nearest, WrapMode::Clamp), 80);
+        assert_eq!(sample(&texture, 0.99, 0.1, Filter::Nearest, WrapMode::Clamp), 230);
+    }
+
+    #[test]
+    fn bilinear_interpolates_between_texel_centers() {
+        let texture = ramp();
+        // テクセル中心ではその値そのもの
+        assert_eq!(sample(&texture, 0.375, 0.75, Filter::Bilinear, WrapMode::Clamp), 40);
+        // 横に並んだ 2 つの中心の中間
+        assert_eq!(sample(&texture, 0.25, 0.75, Filter::Bilinear, WrapMode::Clamp), 20);
+        // 4 つの中心の中間
+        let value = sample(&texture, 0.5, 0.5, Filter::Bilinear, WrapMode::Clamp);
+        assert!(value.abs_diff(((40 + 80 + 210 + 220) / 4) as u8) <= 1, "{value}");
+    }
+
+    #[test]
+    fn wrap_modes_outside_unit_square() {
+        let texture = ramp();
+        let row = |u: f32, wrap: WrapMode| sample(&texture, u, 0.9, Filter::Nearest, wrap);
+
+        // Repeat は 1 周期ずらした位置と同じ
+        assert_eq!(row(1.1, WrapMode::Repeat), 0);
+        assert_eq!(row(-0.1, WrapMode::Repeat), 120);
+        // Clamp は端のテクセルを伸ばす
+        assert_eq!(row(1.6, WrapMode::Clamp), 120);
+        assert_eq!(row(-0.6, WrapMode::Clamp), 0);
+        // Mirror は端で折り返す
+        assert_eq!(row(1.1, WrapMode::Mirror), 120);
+        assert_eq!(row(1.6, WrapMode::Mirror), 40);
+        assert_eq!(row(-0.1, WrapMode::Mirror), 0);
+
+        // バイリニアは端で反対側 (Repeat) か同じテクセル (Clamp) と混ざる
+        let edge = |wrap: WrapMode| sample(&texture, 0.0, 0.75, Filter::Bilinear, wrap);
+        assert_eq!(edge(WrapMode::Repeat), 60);
+        assert_eq!(edge(WrapMode::Clamp), 0);
+        assert_eq!(edge(WrapMode::Mirror), 0);
+    }
+
+    #[test]
+    fn mip_chain_halves_down_to_one_texel() {
+        let texture = Texture::from_pixels(5, 3, vec![gray(0); 15]);
+        let sizes: Vec<_> = texture.levels.iter().map(|l| (l.width, l.height)).collect();
+        assert_eq!(sizes, [(5, 3), (2, 1), (1, 1)]);
+
+        let checker = Texture::checker(64, 8, Color::WHITE, Color::BLACK);
+        assert_eq!(checker.levels.len(), 7);
+    }
+
+    #[test]
+    fn mip_levels_average_2x2_blocks() {
+        let texture = ramp();
+        let level = &texture.levels[1];
+        assert_eq!((level.width, level.height), (2, 1));
+        // (0 + 40 + 200 + 210) / 4 と (80 + 120 + 220 + 230) / 4 を四捨五入
+        assert_eq!(level.texel(0, 0).r, 113);
+        assert_eq!(level.texel(1, 0).r, 163);
+        assert_eq!(texture.levels[2].texel(0, 0).r, 138);
+
+        // 細かい市松模様は縮小すると灰色になる
+        let checker = Texture::checker(16, 16, Color::WHITE, Color::BLACK);
+        assert_eq!(checker.levels[1].texel(3, 5).r, 128);
+    }
+
+    #[test]
+    fn lod_follows_uv_derivatives() {
+        let texture = Texture::checker(64, 8, Color::WHITE, Color::BLACK);
+        let texel = 1.0 / 64.0;
+        // 1 ピクセルで 1 テクセル進むなら元画像
+        let lod = texture.lod(Vec2::new(texel, 0.0), Vec2::new(0.0, texel));
+        assert!(lod.abs() < 1e-4, "{lod}");
+        // 4 テクセルなら 2 段縮小したレベル。大きい方の軸で決まる
+        let lod = texture.lod(Vec2::new(texel, 0.0), Vec2::new(0.0, 4.0 * texel));
+        assert!((lod - 2.0).abs() < 1e-4, "{lod}");
+        // 拡大時と微分が 0 のときは 0 以下
+        assert!(texture.lod(Vec2::new(texel * 0.5, 0.0), Vec2::new(0.0, 0.0)) < 0.0);
+        assert_eq!(texture.lod(Vec2::new(0.0, 0.0), Vec2::new(0.0, 0.0)), 0.0);
+    }
+
+    #[test]
+    fn mip_filters_select_and_blend_levels() {
+        let texture = ramp();
+        let uv = Vec2::new(0.1, 0.9);
+        let nearest = Sampler::new(Filter::Nearest, WrapMode::Clamp);
+        let none = Sampler {
+            mip_filter: MipFilter::None,
+            ..nearest
+        };
+        let linear = Sampler {
+            mip_filter: MipFilter::Linear,
+            ..nearest
+        };
+
+        assert_eq!(texture.sample_lod(uv, 0.4, &nearest).r, 0);
+        assert_eq!(texture.sample_lod(uv, 0.6, &nearest).r, 113);
+        assert_eq!(texture.sample_lod(uv, 1.6, &none).r, 0);
+        // 範囲外の LOD は最小のレベルに切り詰める
+        assert_eq!(texture.sample_lod(uv, 9.0, &nearest).r, 138);
+        // レベル 0 (0) とレベル 1 (113) の中間
+        let value = texture.sample_lod(uv, 0.5, &linear).r;
+        assert!(value.abs_diff(56) <= 1, "{value}");
+    }
+
+    #[test]
+    fn wrap_modes_apply_at_every_mip_level() {
+        let texture = ramp();
+        let lod_sample = |u: f32, wrap: WrapMode| {
+            let sampler = Sampler::new(Filter::Nearest, wrap);
+            texture.sample_lod(Vec2::new(u, 0.5), 1.0, &sampler).r
+        };
+        // レベル 1 は 2x1 の (113, 163)
+        assert_eq!(lod_sample(1.25, WrapMode::Repeat), 113);
+        assert_eq!(lod_sample(-0.25, WrapMode::Repeat), 163);
+        assert_eq!(lod_sample(1.25, WrapMode::Clamp), 163);
+        assert_eq!(lod_sample(-0.25, WrapMode::Clamp), 113);
+        assert_eq!(lod_sample(1.25, WrapMode::Mirror), 163);
+        assert_eq!(lod_sample(-0.25, WrapMode::Mirror), 113);
+        assert_eq!(lod_sample(-0.75, WrapMode::Mirror), 163);
+    }
+}